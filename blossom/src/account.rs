@@ -25,4 +25,10 @@ impl Account {
             roles,
         }
     }
+
+    /// Whether the account can moderate other players. Admins can do
+    /// everything moderators can.
+    pub fn is_moderator(&self) -> bool {
+        self.roles.contains(&Role::Admin) || self.roles.contains(&Role::Moderator)
+    }
 }
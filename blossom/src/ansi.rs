@@ -0,0 +1,487 @@
+use crate::terminal::ColorSupport;
+
+/// Helpers for working with text containing ANSI escape sequences, such as the
+/// styled strings produced by `iridescent`. Escape sequences take up no space
+/// on the client's screen, so they must be skipped over whenever we measure or
+/// lay out text.
+const ESC: char = '\x1b';
+
+/// The default xterm palette for the 16 basic colors. Used to find the closest
+/// basic color when downgrading, and to expand 256-color indexes below 16.
+const PALETTE: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// The channel levels used by the 6x6x6 color cube in the 256-color palette.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Color {
+    Rgb(u8, u8, u8),
+    Indexed(u8),
+}
+
+/// Returns the number of characters in the string which are actually displayed,
+/// ignoring ANSI escape sequences.
+pub fn visible_width(s: &str) -> usize {
+    let mut width = 0;
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        if c == ESC {
+            skip_sequence(&mut chars);
+        } else {
+            width += 1;
+        }
+    }
+
+    width
+}
+
+/// Advances past the remainder of an escape sequence, assuming the leading ESC
+/// has already been consumed. CSI sequences (ESC [) end with a byte in the
+/// range `@` to `~`; anything else is a two-character sequence.
+fn skip_sequence(chars: &mut std::str::Chars) {
+    if chars.next() == Some('[') {
+        for c in chars.by_ref() {
+            if ('@'..='~').contains(&c) {
+                break;
+            }
+        }
+    }
+}
+
+/// Renders styled text for a client with the given color support. Truecolor
+/// clients receive the text unchanged, clients with limited color get every
+/// color converted to the closest one they can display, and clients without
+/// color support get all escape sequences stripped.
+pub fn render(text: &str, support: ColorSupport) -> String {
+    if support == ColorSupport::TrueColor {
+        return text.to_string();
+    }
+
+    let mut output = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c != ESC {
+            output.push(c);
+            continue;
+        }
+
+        if support == ColorSupport::None {
+            skip_sequence(&mut chars);
+            continue;
+        }
+
+        // Only SGR sequences contain colors; anything else is passed through
+        // untouched.
+        let sequence = read_sequence(&mut chars);
+        match sgr_params(&sequence) {
+            Some(params) => output.push_str(&downgrade_sgr(params, support)),
+            None => output.push_str(&sequence),
+        }
+    }
+
+    output
+}
+
+/// Converts styled text to HTML for clients which render text in a browser.
+/// SGR sequences become `<span>` elements with inline styles, any other escape
+/// sequences are dropped, and the text itself is HTML escaped.
+pub fn to_html(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut style = Style::default();
+    let mut open = false;
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ESC => {
+                let sequence = read_sequence(&mut chars);
+                let Some(params) = sgr_params(&sequence) else {
+                    continue;
+                };
+
+                style.apply(params);
+
+                if open {
+                    output.push_str("</span>");
+                    open = false;
+                }
+
+                let css = style.css();
+                if !css.is_empty() {
+                    output.push_str(&format!("<span style=\"{css}\">"));
+                    open = true;
+                }
+            }
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            '\'' => output.push_str("&#39;"),
+            c => output.push(c),
+        }
+    }
+
+    if open {
+        output.push_str("</span>");
+    }
+
+    output
+}
+
+/// The text style built up from SGR sequences, used when converting to HTML.
+#[derive(Debug, Default)]
+struct Style {
+    bold: bool,
+    dim: bool,
+    italic: bool,
+    underline: bool,
+    foreground: Option<(u8, u8, u8)>,
+    background: Option<(u8, u8, u8)>,
+}
+
+impl Style {
+    fn apply(&mut self, params: &str) {
+        let mut codes = params
+            .split(';')
+            .map(|p| p.parse::<u16>().unwrap_or_default());
+        let basic = |index: u16| index_to_rgb(u8::try_from(index).unwrap_or_default());
+
+        while let Some(code) = codes.next() {
+            match code {
+                0 => *self = Self::default(),
+                1 => self.bold = true,
+                2 => self.dim = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                22 => {
+                    self.bold = false;
+                    self.dim = false;
+                }
+                23 => self.italic = false,
+                24 => self.underline = false,
+                30..=37 => self.foreground = Some(basic(code - 30)),
+                90..=97 => self.foreground = Some(basic(code - 90 + 8)),
+                39 => self.foreground = None,
+                40..=47 => self.background = Some(basic(code - 40)),
+                100..=107 => self.background = Some(basic(code - 100 + 8)),
+                49 => self.background = None,
+                38 | 48 => {
+                    let rgb = match parse_extended(&mut codes) {
+                        Some(Color::Rgb(r, g, b)) => Some((r, g, b)),
+                        Some(Color::Indexed(i)) => Some(index_to_rgb(i)),
+                        None => None,
+                    };
+
+                    if code == 38 {
+                        self.foreground = rgb;
+                    } else {
+                        self.background = rgb;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn css(&self) -> String {
+        let mut css = Vec::new();
+
+        if self.bold {
+            css.push("font-weight:bold".to_string());
+        }
+
+        if self.dim {
+            css.push("opacity:0.7".to_string());
+        }
+
+        if self.italic {
+            css.push("font-style:italic".to_string());
+        }
+
+        if self.underline {
+            css.push("text-decoration:underline".to_string());
+        }
+
+        if let Some((r, g, b)) = self.foreground {
+            css.push(format!("color:#{r:02x}{g:02x}{b:02x}"));
+        }
+
+        if let Some((r, g, b)) = self.background {
+            css.push(format!("background-color:#{r:02x}{g:02x}{b:02x}"));
+        }
+
+        css.join(";")
+    }
+}
+
+/// Reads the remainder of an escape sequence, assuming the leading ESC has
+/// already been consumed, and returns the full sequence including the ESC.
+fn read_sequence(chars: &mut std::str::Chars) -> String {
+    let mut sequence = String::from(ESC);
+
+    if let Some(next) = chars.next() {
+        sequence.push(next);
+
+        if next == '[' {
+            for c in chars.by_ref() {
+                sequence.push(c);
+
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        }
+    }
+
+    sequence
+}
+
+/// Returns the parameters of an SGR (ESC [ ... m) sequence, or `None` if the
+/// sequence is anything else.
+fn sgr_params(sequence: &str) -> Option<&str> {
+    sequence
+        .strip_prefix("\x1b[")
+        .and_then(|s| s.strip_suffix('m'))
+}
+
+/// Parses the color following an extended color code (38 or 48), which is
+/// either `2;r;g;b` or `5;index`.
+fn parse_extended(codes: &mut impl Iterator<Item = u16>) -> Option<Color> {
+    let mut channel = || codes.next().and_then(|c| u8::try_from(c).ok());
+
+    match channel() {
+        Some(2) => match (channel(), channel(), channel()) {
+            (Some(r), Some(g), Some(b)) => Some(Color::Rgb(r, g, b)),
+            _ => None,
+        },
+        Some(5) => channel().map(Color::Indexed),
+        _ => None,
+    }
+}
+
+/// Rewrites the parameters of an SGR sequence so any extended colors fit the
+/// given color support.
+fn downgrade_sgr(params: &str, support: ColorSupport) -> String {
+    let mut codes = params
+        .split(';')
+        .map(|p| p.parse::<u16>().unwrap_or_default());
+    let mut output = Vec::new();
+
+    while let Some(code) = codes.next() {
+        if code != 38 && code != 48 {
+            output.push(code.to_string());
+            continue;
+        }
+
+        if let Some(color) = parse_extended(&mut codes) {
+            output.push(color_code(code == 48, color, support));
+        }
+    }
+
+    format!("\x1b[{}m", output.join(";"))
+}
+
+/// Returns the SGR parameters for a foreground (or background) color.
+fn color_code(background: bool, color: Color, support: ColorSupport) -> String {
+    let base = if background { 48 } else { 38 };
+
+    match (support, color) {
+        (ColorSupport::TrueColor, Color::Rgb(r, g, b)) => format!("{base};2;{r};{g};{b}"),
+        (ColorSupport::TrueColor | ColorSupport::Ansi256, Color::Indexed(i)) => {
+            format!("{base};5;{i}")
+        }
+        (ColorSupport::Ansi256, Color::Rgb(r, g, b)) => format!("{base};5;{}", rgb_to_256(r, g, b)),
+        (_, color) => {
+            let index = match color {
+                Color::Indexed(i) if i < 16 => i,
+                Color::Indexed(i) => nearest_basic(index_to_rgb(i)),
+                Color::Rgb(r, g, b) => nearest_basic((r, g, b)),
+            };
+            let code = match (index < 8, background) {
+                (true, false) => 30 + index,
+                (true, true) => 40 + index,
+                (false, false) => 90 + index - 8,
+                (false, true) => 100 + index - 8,
+            };
+
+            code.to_string()
+        }
+    }
+}
+
+/// Converts an RGB color to the closest color in the 256-color palette, using
+/// either the grayscale ramp or the color cube.
+fn rgb_to_256(r: u8, g: u8, b: u8) -> u8 {
+    if r == g && g == b {
+        return match r {
+            0..=7 => 16,
+            249..=255 => 231,
+            _ => 232 + ((u16::from(r) - 8) * 24 / 247) as u8,
+        };
+    }
+
+    let level = |c: u8| (u16::from(c) * 5 + 127) / 255;
+
+    (16 + 36 * level(r) + 6 * level(g) + level(b)) as u8
+}
+
+/// Converts a 256-color palette index back to RGB.
+fn index_to_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => PALETTE.get(usize::from(index)).copied().unwrap_or_default(),
+        16..=231 => {
+            let i = usize::from(index - 16);
+            let level = |n: usize| CUBE_LEVELS.get(n).copied().unwrap_or_default();
+
+            (level(i / 36), level((i / 6) % 6), level(i % 6))
+        }
+        _ => {
+            let gray = 8 + (index - 232) * 10;
+
+            (gray, gray, gray)
+        }
+    }
+}
+
+/// Returns the index of the basic color closest to the given RGB color.
+fn nearest_basic((r, g, b): (u8, u8, u8)) -> u8 {
+    let distance = |(pr, pg, pb): &(u8, u8, u8)| {
+        let dr = i32::from(r) - i32::from(*pr);
+        let dg = i32::from(g) - i32::from(*pg);
+        let db = i32::from(b) - i32::from(*pb);
+
+        dr * dr + dg * dg + db * db
+    };
+
+    PALETTE
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, color)| distance(color))
+        .map(|(i, _)| i as u8)
+        .unwrap_or_default()
+}
+
+/// Word wraps the text so no line is wider than `width` visible characters.
+/// Existing line breaks and spacing are kept, and escape sequences count as
+/// zero width. Words longer than the width are left on a line of their own
+/// rather than being split.
+pub fn wrap(text: &str, width: usize) -> String {
+    let mut output = String::with_capacity(text.len());
+
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            output.push('\n');
+        }
+
+        let mut current = 0;
+
+        for (j, word) in line.split(' ').enumerate() {
+            let word_width = visible_width(word);
+
+            if j > 0 {
+                if current > 0 && current + 1 + word_width > width {
+                    output.push('\n');
+                    current = 0;
+                } else {
+                    output.push(' ');
+                    current += 1;
+                }
+            }
+
+            output.push_str(word);
+            current += word_width;
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use iridescent::Styled;
+
+    use super::*;
+
+    #[test]
+    fn width_ignores_escapes() {
+        let styled = format!("{}", "Grassy Hill".foreground("#3e8948").bold());
+
+        assert!(styled.len() > 11);
+        assert_eq!(visible_width(&styled), 11);
+    }
+
+    #[test]
+    fn render_strips_without_color() {
+        let styled = format!("{}", "Grassy Hill".foreground("#3e8948").bold());
+
+        assert_eq!(render(&styled, ColorSupport::None), "Grassy Hill");
+    }
+
+    #[test]
+    fn render_downgrades_truecolor() {
+        let text = "\x1b[1;38;2;228;59;68mDanger\x1b[0m";
+
+        assert_eq!(
+            render(text, ColorSupport::Ansi256),
+            "\x1b[1;38;5;167mDanger\x1b[0m"
+        );
+        assert_eq!(
+            render(text, ColorSupport::Ansi16),
+            "\x1b[1;31mDanger\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn html_escapes_and_styles() {
+        let text = "\x1b[1;38;2;62;137;72mGrassy <Hill>\x1b[0m & more";
+
+        assert_eq!(
+            to_html(text),
+            "<span style=\"font-weight:bold;color:#3e8948\">Grassy &lt;Hill&gt;</span> &amp; more"
+        );
+    }
+
+    #[test]
+    fn wrap_long_line() {
+        let text = "The quick brown fox jumps over the lazy dog";
+
+        assert_eq!(
+            wrap(text, 16),
+            "The quick brown\nfox jumps over\nthe lazy dog"
+        );
+    }
+
+    #[test]
+    fn wrap_keeps_line_breaks() {
+        let text = "A short line\nand another";
+
+        assert_eq!(wrap(text, 80), text);
+    }
+
+    #[test]
+    fn wrap_styled_text() {
+        let name = format!("{}", "Blossom".foreground("#0099db"));
+        let text = format!("Welcome back, {name}!");
+
+        assert_eq!(wrap(&text, 14), format!("Welcome back,\n{name}!"));
+    }
+}
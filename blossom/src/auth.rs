@@ -7,15 +7,18 @@ use argon2::{
 use iridescent::{constants::RED, Styled};
 use nectar::{event::TelnetEvent, option::TelnetOption};
 use sqlx::postgres::PgPool;
+use unicode_normalization::UnicodeNormalization;
 
 use crate::{
     account::Account,
-    blossom_log,
+    bans, blossom_log,
     connection::Connection,
     entity::EntityId,
     error::{Error, ErrorType, Result},
+    ignores,
     logging::{Action, Kind, Loggable},
     player::{PartialPlayer, Player},
+    rate_limit::{RateLimiter, Verdict, FLOOD_DISCONNECT, FLOOD_WARNING},
     role::Role,
     theme,
     utils::{capitalize, is_http},
@@ -72,7 +75,9 @@ async fn login(name: &str, password: &str, addr: IpAddr, pg: &PgPool) -> Result<
     // prompted to enter their username at the start, thus we can guarantee that
     // a single record will exist if this function is called.
     let record = sqlx::query!(
-        r#"select p.id, p.name, p.position, p.health, p.max_health, p.mana, p.max_mana, p.xp, p.level, p.afk, p.brief, a.id as "account_id", a.password_hash, a.email as "email?", a.roles
+        r#"select p.id, p.name, p.position, p.health, p.max_health, p.mana, p.max_mana, p.xp, p.level, p.afk, p.brief, p.wrap_width, p.channels,
+            (select count(*) from mail m where m.recipient = p.id and not m.read) as "unread_mail!",
+            a.id as "account_id", a.password_hash, a.email as "email?", a.roles
         from players p 
         join accounts a on p.account_id = a.id 
         where p.name = $1"#,
@@ -105,8 +110,14 @@ async fn login(name: &str, password: &str, addr: IpAddr, pg: &PgPool) -> Result<
             level: record.level,
             brief: record.brief,
             afk: record.afk,
+            wrap_width: record.wrap_width,
+            channels: record.channels,
+            reply_to: None,
+            unread_mail: record.unread_mail,
+            ignores: ignores::load(record.account_id, pg).await?,
             dirty: false,
             seen: true,
+            linkdead: None,
         })
     } else {
         Err(Error::new(ErrorType::Authentication, "Invalid credentials"))
@@ -154,8 +165,13 @@ async fn is_first_account(pg: &PgPool) -> Result<bool> {
 /// This function will return additionally returns a special flag, `restart`,
 /// which will restart the authentication process if the player. We need to do
 /// this because some responses should drop the connection instead.
-pub async fn authenticate(conn: &mut Connection, pg: PgPool) -> Result<Option<Player>> {
-    let name = match get_name(conn).await {
+pub async fn authenticate(
+    conn: &mut Connection,
+    limiter: &mut RateLimiter,
+    pg: PgPool,
+    ban_message: &str,
+) -> Result<Option<Player>> {
+    let name = match get_name(conn, limiter).await {
         Ok(name) => name,
         Err(e) => return Err(e),
     };
@@ -163,10 +179,20 @@ pub async fn authenticate(conn: &mut Connection, pg: PgPool) -> Result<Option<Pl
     let exists = name_exists(&name, &pg).await?;
 
     if exists {
-        let password = get_password(conn).await?;
+        let password = get_password(conn, limiter).await?;
         let partial_player = login(&name, &password, conn.ip(), &pg).await;
 
         if let Ok(player) = partial_player {
+            // The account is only known once the player has logged in, so
+            // account bans can't be checked any earlier.
+            if let Some(ban) = bans::find_account_ban(player.account.id, &pg).await? {
+                conn.account_id = Some(player.account.id);
+                blossom_log!(Kind::Banned, format!("#{}", ban.id), conn);
+                conn.send_message(&ban.rejection(ban_message)).await?;
+
+                return Ok(None);
+            }
+
             blossom_log!(Kind::Join, conn);
 
             Ok(Some(player))
@@ -177,7 +203,7 @@ pub async fn authenticate(conn: &mut Connection, pg: PgPool) -> Result<Option<Pl
             Ok(None)
         }
     } else {
-        let password = set_password(conn).await?;
+        let password = set_password(conn, limiter).await?;
         let partial_player = create(&name, &password, &pg).await?;
         let colored_name = &name.foreground(theme::BLUE).bold();
 
@@ -235,19 +261,54 @@ pub async fn authenticate(conn: &mut Connection, pg: PgPool) -> Result<Option<Pl
             level: 1,
             brief: false,
             afk: false,
+            wrap_width: 80,
+            channels: None,
+            reply_to: None,
+            unread_mail: 0,
+            ignores: Vec::new(),
             dirty: false,
             seen: false,
+            linkdead: None,
         }))
     }
 }
 
+/// Reads the next line from the client. Input at the login prompts is held to
+/// the same rate limit as in-game input, but is never logged, as it may be a
+/// password.
+async fn next_line(conn: &mut Connection, limiter: &mut RateLimiter) -> Result<Option<String>> {
+    loop {
+        let Some(msg) = conn.try_next().await else {
+            return Ok(None);
+        };
+
+        match limiter.check() {
+            Verdict::Allow => return Ok(Some(msg)),
+            Verdict::Warn => {
+                blossom_log!(Kind::RateLimitWarning, conn);
+                conn.send_message(FLOOD_WARNING).await?;
+            }
+            Verdict::Drop => blossom_log!(Kind::RateLimitDrop, conn),
+            Verdict::Disconnect => {
+                blossom_log!(Kind::RateLimitDisconnect, conn);
+                conn.send_message(FLOOD_DISCONNECT).await?;
+
+                return Err(Error {
+                    kind: ErrorType::Authentication,
+                    message: "Disconnected for flooding.".to_string(),
+                });
+            }
+        }
+    }
+}
+
 /// Prompt the player for their name and returns the input.
-async fn get_name(conn: &mut Connection) -> Result<String> {
+async fn get_name(conn: &mut Connection, limiter: &mut RateLimiter) -> Result<String> {
     let name = loop {
         conn.send_message("What is your name? If you are new, enter the name you wish to use.")
             .await?;
 
-        if let Some(msg) = conn.try_next().await {
+        if let Some(msg) = next_line(conn, limiter).await? {
             // Because this is the first frame we receive from the client, we
             // have to check if it contains HTTP traffic, and if so, drop it
             // silently.
@@ -258,8 +319,19 @@ async fn get_name(conn: &mut Connection) -> Result<String> {
                 });
             }
 
-            let msg = msg.trim();
-            if !validate_username(msg) {
+            // Names are stored NFC normalized, so the same name can't be
+            // registered twice by typing its accents differently.
+            let msg = msg.trim().nfc().collect::<String>();
+
+            // MUD listing crawlers which don't negotiate MSSP send this at the
+            // login prompt instead. They disconnect once they have the reply.
+            if msg == "MSSP-REQUEST" {
+                conn.send_mssp_reply().await?;
+
+                continue;
+            }
+
+            if !validate_username(&msg) {
                 conn.send_message(&format!(
                     "{}",
                     "Name should be between 3 and 16 alphabetical characters.".foreground(RED)
@@ -269,7 +341,7 @@ async fn get_name(conn: &mut Connection) -> Result<String> {
                 continue;
             }
 
-            break capitalize(msg);
+            break capitalize(&msg);
         }
     };
 
@@ -277,7 +349,7 @@ async fn get_name(conn: &mut Connection) -> Result<String> {
 }
 
 /// Prompts an existing player for their password and returns the input.
-async fn get_password(conn: &mut Connection) -> Result<String> {
+async fn get_password(conn: &mut Connection, limiter: &mut RateLimiter) -> Result<String> {
     let mut failure_count = 0;
 
     // ECHO off
@@ -294,7 +366,7 @@ async fn get_password(conn: &mut Connection) -> Result<String> {
 
         conn.send_message("What is your password?").await?;
 
-        if let Some(msg) = conn.try_next().await {
+        if let Some(msg) = next_line(conn, limiter).await? {
             let msg = msg.trim();
 
             if msg.is_empty() {
@@ -321,13 +393,13 @@ async fn get_password(conn: &mut Connection) -> Result<String> {
 /// Prompts a new player for their password and returns the input. This will
 /// also ask if the player wishes to create a new character with the name they
 /// provided.
-async fn set_password(conn: &mut Connection) -> Result<String> {
+async fn set_password(conn: &mut Connection, limiter: &mut RateLimiter) -> Result<String> {
     loop {
         // Set the players password -- we will turn off echo for this.
         conn.send_message("Character not found. Create a new character with this name? [Y/n]")
             .await?;
 
-        if let Some(msg) = conn.try_next().await {
+        if let Some(msg) = next_line(conn, limiter).await? {
             match msg.to_lowercase().as_str() {
                 "y" | "yes" | "" => break,
                 "n" | "no" => {
@@ -348,7 +420,7 @@ async fn set_password(conn: &mut Connection) -> Result<String> {
         conn.send_message("What will your password be? [`q` to quit]")
             .await?;
 
-        if let Some(msg) = conn.try_next().await {
+        if let Some(msg) = next_line(conn, limiter).await? {
             let msg = msg.trim();
 
             if msg == "exit" {
@@ -378,7 +450,42 @@ async fn set_password(conn: &mut Connection) -> Result<String> {
     Ok(password)
 }
 
-/// Returns a bool indicating whether or not the username is valid.
+/// Returns a bool indicating whether or not the username is valid. Names can
+/// use Latin letters, including accented ones, and are measured in characters
+/// rather than bytes. The username should already be NFC normalized.
 fn validate_username(username: &str) -> bool {
-    username.len() >= 3 && username.len() <= 16 && username.chars().all(|c| c.is_ascii_alphabetic())
+    let length = username.chars().count();
+
+    (3..=16).contains(&length) && username.chars().all(is_latin_letter)
+}
+
+/// Whether a character is a Latin letter, including the accented letters in
+/// Latin-1 and Latin Extended-A and B. Names are kept to one script, so letters
+/// from another alphabet which look the same (such as the Cyrillic "А") can't
+/// be used to pass as someone else.
+fn is_latin_letter(c: char) -> bool {
+    c.is_ascii_alphabetic() || (matches!(c, '\u{c0}'..='\u{24f}') && c.is_alphabetic())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unicode_usernames() {
+        assert!(validate_username("Zoë"));
+        assert!(validate_username("Łukasz"));
+        assert!(validate_username("ÉléonoreÉléonore"));
+        assert!(!validate_username("Zo"));
+        assert!(!validate_username("Zoë2"));
+        assert!(!validate_username("ÉléonoreÉléonoreX"));
+    }
+
+    #[test]
+    fn mixed_script_usernames() {
+        // The first letter is the Cyrillic "А".
+        assert!(!validate_username("\u{410}lice"));
+        assert!(!validate_username("Σοφία"));
+        assert!(!validate_username("Zo×ë"));
+    }
 }
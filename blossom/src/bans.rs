@@ -0,0 +1,475 @@
+use std::net::IpAddr;
+
+use flume::Sender;
+use sqlx::{types::ipnetwork::IpNetwork, PgPool};
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    account::Account,
+    error::Result,
+    logging::{Action, Kind, Loggable},
+    player::Player,
+    role::Role,
+    utils::normalize_name,
+};
+
+// The widest ranges which can be banned. Anything wider would take out a
+// whole provider, or everyone, rather than a single abuser.
+const MIN_PREFIX_V4: u8 = 16;
+const MIN_PREFIX_V6: u8 = 32;
+
+/// What a ban applies to.
+#[derive(Clone, Debug)]
+pub enum BanTarget {
+    // A single address or a CIDR range, eg. `203.0.113.0/24`.
+    Network(IpNetwork),
+    // The account the named player belongs to, including its other characters.
+    Player(String),
+}
+
+impl BanTarget {
+    /// Parses an address or CIDR range, and treats anything else as a player
+    /// name.
+    pub fn parse(s: &str) -> Self {
+        match s.parse::<IpNetwork>() {
+            Ok(network) => BanTarget::Network(network),
+            Err(_) => BanTarget::Player(normalize_name(s)),
+        }
+    }
+
+    /// Whether the target is a range too wide to ban, such as `0.0.0.0/0`.
+    pub fn is_too_broad(&self) -> bool {
+        match self {
+            BanTarget::Network(IpNetwork::V4(network)) => network.prefix() < MIN_PREFIX_V4,
+            BanTarget::Network(IpNetwork::V6(network)) => network.prefix() < MIN_PREFIX_V6,
+            BanTarget::Player(_) => false,
+        }
+    }
+}
+
+impl std::fmt::Display for BanTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BanTarget::Network(network) => write!(f, "{network}"),
+            BanTarget::Player(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+/// A request from a moderator, carried out by the broker as it holds the
+/// database pool.
+#[derive(Clone, Debug)]
+pub enum BanRequest {
+    // A ban without a duration is permanent.
+    Add {
+        target: BanTarget,
+        duration: Option<Duration>,
+        reason: String,
+    },
+    Lift(i32),
+    List,
+}
+
+impl std::fmt::Display for BanRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BanRequest::Add { target, .. } => write!(f, "Add {target}"),
+            BanRequest::Lift(id) => write!(f, "Lift {id}"),
+            BanRequest::List => write!(f, "List"),
+        }
+    }
+}
+
+/// Who a ban covers, once it has been stored, so the game can kick anyone it
+/// covers who is online.
+#[derive(Clone, Debug)]
+pub struct Banned {
+    pub network: Option<IpNetwork>,
+    pub account_id: Option<i32>,
+    pub reason: String,
+}
+
+impl Banned {
+    pub fn covers(&self, player: &Player) -> bool {
+        self.network
+            .is_some_and(|network| network.contains(player._addr))
+            || self.account_id == Some(player.account.id)
+    }
+}
+
+/// What the broker tells the game after handling a `BanRequest`.
+pub struct Outcome {
+    // The message for the moderator who made the request.
+    pub reply: String,
+    // Who a new ban covers, if one was stored.
+    pub banned: Option<Banned>,
+}
+
+impl From<String> for Outcome {
+    fn from(reply: String) -> Self {
+        Outcome {
+            reply,
+            banned: None,
+        }
+    }
+}
+
+/// The staff member behind a `BanRequest`, who is recorded in the action logs.
+#[derive(Clone, Debug)]
+pub struct Moderator {
+    pub account_id: i32,
+    pub addr: IpAddr,
+}
+
+/// An active ban, as stored in the database.
+#[derive(Debug)]
+pub struct Ban {
+    pub id: i32,
+    pub ip_range: Option<IpNetwork>,
+    pub player: Option<String>,
+    pub reason: String,
+    pub expires_on: Option<OffsetDateTime>,
+}
+
+impl Ban {
+    /// The message shown to a banned connection before it is closed.
+    pub fn rejection(&self, message: &str) -> String {
+        format!(
+            "{message}\nReason: {}\nExpires: {}",
+            self.reason,
+            expiry(self.expires_on)
+        )
+    }
+}
+
+impl std::fmt::Display for Ban {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let target = match (&self.ip_range, &self.player) {
+            (Some(network), _) => network.to_string(),
+            (None, Some(name)) => name.clone(),
+            (None, None) => "unknown".to_string(),
+        };
+
+        write!(
+            f,
+            "#{:<5} {:<20} {:<12} {}",
+            self.id,
+            target,
+            expiry(self.expires_on),
+            self.reason
+        )
+    }
+}
+
+/// Parses a ban duration such as `30m`, `12h`, `7d` or `2w`. Returns
+/// `Some(None)` for a permanent ban, and `None` if the duration is invalid.
+pub fn parse_duration(s: &str) -> Option<Option<Duration>> {
+    if matches!(s, "perm" | "permanent") {
+        return Some(None);
+    }
+
+    let split = s.len().checked_sub(1)?;
+    let (amount, unit) = (s.get(..split)?, s.get(split..)?);
+    let amount = amount.parse::<i64>().ok().filter(|n| *n > 0)?;
+
+    let duration = match unit {
+        "m" => Duration::minutes(amount),
+        "h" => Duration::hours(amount),
+        "d" => Duration::days(amount),
+        "w" => Duration::weeks(amount),
+        _ => return None,
+    };
+
+    Some(Some(duration))
+}
+
+/// Describes when a ban expires, relative to now.
+fn expiry(expires_on: Option<OffsetDateTime>) -> String {
+    let Some(expires_on) = expires_on else {
+        return "never".to_string();
+    };
+
+    let remaining = expires_on - OffsetDateTime::now_utc();
+    if remaining.whole_days() > 0 {
+        format!("in {}d", remaining.whole_days())
+    } else if remaining.whole_hours() > 0 {
+        format!("in {}h", remaining.whole_hours())
+    } else {
+        format!("in {}m", remaining.whole_minutes().max(1))
+    }
+}
+
+/// Returns the active ban covering an address, if there is one.
+pub async fn find_ip_ban(addr: IpAddr, pg: &PgPool) -> Result<Option<Ban>> {
+    let ban = sqlx::query_as!(
+        Ban,
+        r#"select b.id, b.ip_range, null::text as "player?", b.reason, b.expires_on
+        from bans b
+        where b.ip_range >>= $1
+        and not b.lifted
+        and (b.expires_on is null or b.expires_on > now())
+        order by b.expires_on desc nulls first
+        limit 1"#,
+        IpNetwork::from(addr),
+    )
+    .fetch_optional(pg)
+    .await?;
+
+    Ok(ban)
+}
+
+/// Returns the active ban on an account, if there is one.
+pub async fn find_account_ban(account_id: i32, pg: &PgPool) -> Result<Option<Ban>> {
+    let ban = sqlx::query_as!(
+        Ban,
+        r#"select b.id, b.ip_range, null::text as "player?", b.reason, b.expires_on
+        from bans b
+        where b.account_id = $1
+        and not b.lifted
+        and (b.expires_on is null or b.expires_on > now())
+        order by b.expires_on desc nulls first
+        limit 1"#,
+        account_id,
+    )
+    .fetch_optional(pg)
+    .await?;
+
+    Ok(ban)
+}
+
+/// Returns every active ban, oldest first.
+async fn list(pg: &PgPool) -> Result<Vec<Ban>> {
+    let bans = sqlx::query_as!(
+        Ban,
+        r#"select b.id, b.ip_range,
+            (select p.name from players p where p.account_id = b.account_id order by p.id limit 1) as "player?",
+            b.reason, b.expires_on
+        from bans b
+        where not b.lifted
+        and (b.expires_on is null or b.expires_on > now())
+        order by b.id"#,
+    )
+    .fetch_all(pg)
+    .await?;
+
+    Ok(bans)
+}
+
+/// The result of trying to store a ban.
+enum Added {
+    Stored(i32, Banned),
+    // The ban wasn't stored, for the reason given.
+    Refused(String),
+}
+
+/// Stores a new ban. Players who don't exist, the moderator's own account and
+/// staff accounts can't be banned.
+async fn add(
+    target: &BanTarget,
+    duration: Option<Duration>,
+    reason: &str,
+    moderator: &Moderator,
+    pg: &PgPool,
+) -> Result<Added> {
+    let (ip_range, account_id) = match target {
+        BanTarget::Network(network) => (Some(*network), None),
+        BanTarget::Player(name) => {
+            let record = sqlx::query!(
+                "select p.account_id, a.roles
+                from players p
+                join accounts a on a.id = p.account_id
+                where p.name = $1",
+                name
+            )
+            .fetch_optional(pg)
+            .await?;
+
+            let Some(record) = record else {
+                return Ok(Added::Refused(format!(
+                    "There is no player named {target}."
+                )));
+            };
+
+            if record.account_id == moderator.account_id {
+                return Ok(Added::Refused("You can't ban yourself.".to_string()));
+            }
+
+            // Staff are whoever the game itself treats as moderators.
+            let roles = record
+                .roles
+                .iter()
+                .filter_map(|role| role.parse::<Role>().ok())
+                .collect();
+            if Account::new(record.account_id, roles).is_moderator() {
+                return Ok(Added::Refused(format!(
+                    "{target} is staff, and can't be banned."
+                )));
+            }
+
+            (None, Some(record.account_id))
+        }
+    };
+
+    let expires_on = duration.map(|d| OffsetDateTime::now_utc() + d);
+
+    let record = sqlx::query!(
+        "insert into bans (ip_range, account_id, reason, banned_by, expires_on)
+        values ($1, $2, $3, $4, $5)
+        returning id",
+        ip_range,
+        account_id,
+        reason,
+        moderator.account_id,
+        expires_on,
+    )
+    .fetch_one(pg)
+    .await?;
+
+    let banned = Banned {
+        network: ip_range,
+        account_id,
+        reason: reason.to_string(),
+    };
+
+    Ok(Added::Stored(record.id, banned))
+}
+
+/// Lifts an active ban. Returns whether there was one to lift.
+async fn lift(id: i32, pg: &PgPool) -> Result<bool> {
+    let record = sqlx::query!(
+        "update bans set lifted = true where id = $1 and not lifted returning id",
+        id
+    )
+    .fetch_optional(pg)
+    .await?;
+
+    Ok(record.is_some())
+}
+
+/// Carries out a moderator's request, auditing any changes, and returns the
+/// message to show them, along with who a new ban covers.
+pub async fn handle(
+    request: BanRequest,
+    moderator: Moderator,
+    pg: &PgPool,
+    tx_logger: &Sender<Action>,
+) -> Outcome {
+    let auditor = Auditor {
+        moderator: &moderator,
+        tx_logger,
+    };
+
+    let result = match request {
+        BanRequest::Add {
+            target,
+            duration,
+            reason,
+        } => add(&target, duration, &reason, &moderator, pg)
+            .await
+            .map(|added| match added {
+                Added::Stored(id, banned) => {
+                    let detail = format!("#{id} {target} ({}): {reason}", describe(duration));
+                    auditor.log(Kind::BanAdded, detail);
+
+                    Outcome {
+                        reply: format!("Ban #{id} added for {target}."),
+                        banned: Some(banned),
+                    }
+                }
+                Added::Refused(reply) => Outcome::from(reply),
+            }),
+        BanRequest::Lift(id) => lift(id, pg).await.map(|lifted| {
+            if lifted {
+                auditor.log(Kind::BanLifted, format!("#{id}"));
+
+                Outcome::from(format!("Ban #{id} lifted."))
+            } else {
+                Outcome::from(format!("There is no active ban #{id}."))
+            }
+        }),
+        BanRequest::List => list(pg).await.map(|bans| {
+            if bans.is_empty() {
+                return Outcome::from("There are no active bans.".to_string());
+            }
+
+            let mut lines = vec![format!(
+                "{:<6} {:<20} {:<12} {}",
+                "ID", "Target", "Expires", "Reason"
+            )];
+            lines.extend(bans.iter().map(ToString::to_string));
+
+            Outcome::from(lines.join("\n"))
+        }),
+    };
+
+    result.unwrap_or_else(|e| {
+        tracing::error!(%e, "Failed to process ban request");
+        Outcome::from("Something went wrong; the ban request was not processed.".to_string())
+    })
+}
+
+/// Describes a ban duration for the action logs.
+fn describe(duration: Option<Duration>) -> String {
+    match duration {
+        Some(duration) => format!("{}m", duration.whole_minutes()),
+        None => "permanent".to_string(),
+    }
+}
+
+/// Identifies the moderator in the action logs.
+struct Auditor<'a> {
+    moderator: &'a Moderator,
+    tx_logger: &'a Sender<Action>,
+}
+
+impl Auditor<'_> {
+    fn log(&self, kind: Kind, detail: String) {
+        let _ = self.tx_logger.send(Action::with_detail(kind, detail, self));
+    }
+}
+
+impl Loggable for Auditor<'_> {
+    fn identifier(&self) -> (IpAddr, Option<i32>) {
+        (self.moderator.addr, Some(self.moderator.account_id))
+    }
+
+    fn get_logger(&self) -> Sender<Action> {
+        self.tx_logger.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_durations() {
+        assert_eq!(parse_duration("30m"), Some(Some(Duration::minutes(30))));
+        assert_eq!(parse_duration("7d"), Some(Some(Duration::days(7))));
+        assert_eq!(parse_duration("perm"), Some(None));
+        assert_eq!(parse_duration("0d"), None);
+        assert_eq!(parse_duration("3y"), None);
+        assert_eq!(parse_duration(""), None);
+    }
+
+    #[test]
+    fn parse_targets() {
+        assert!(
+            matches!(BanTarget::parse("203.0.113.7"), BanTarget::Network(n) if n.prefix() == 32)
+        );
+        assert!(
+            matches!(BanTarget::parse("203.0.113.0/24"), BanTarget::Network(n) if n.prefix() == 24)
+        );
+        assert!(matches!(BanTarget::parse("aLiCe"), BanTarget::Player(name) if name == "Alice"));
+    }
+
+    #[test]
+    fn broad_ranges() {
+        assert!(BanTarget::parse("0.0.0.0/0").is_too_broad());
+        assert!(BanTarget::parse("10.0.0.0/8").is_too_broad());
+        assert!(!BanTarget::parse("203.0.0.0/16").is_too_broad());
+        assert!(BanTarget::parse("::/0").is_too_broad());
+        assert!(!BanTarget::parse("2001:db8::/48").is_too_broad());
+        assert!(!BanTarget::parse("Alice").is_too_broad());
+    }
+}
@@ -1,21 +1,63 @@
-use std::sync::Arc;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
-use dashmap::DashMap;
+use dashmap::{mapref::entry::Entry, DashMap};
 use flume::{Receiver, Sender};
+use futures::FutureExt;
 use sqlx::PgPool;
 
 use crate::{
-    error::{Error, ErrorType, Result},
+    bans::{self, BanRequest, Moderator},
+    error::Result,
     event::{ClientEvent, Event, GameEvent},
-    player::PlayerId,
+    ignores::{self, IgnoreRequest},
+    logging::Action,
+    mail::{self, MailRequest},
+    peer_queue::{Delivery, PeerSender},
+    player::{Player, PlayerId},
     response::Response,
+    tells,
 };
 
+// How long the supervisor waits before restarting a failed broker loop.
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+// How many times a player save is attempted before giving up, and how long to
+// wait between attempts.
+const SAVE_ATTEMPTS: u32 = 3;
+const SAVE_RETRY_DELAY: Duration = Duration::from_secs(2);
+
 pub struct Broker {
     pg: PgPool,
-    tx_peers: DashMap<i32, Sender<Event>>,
+    tx_peers: DashMap<i32, PeerSender>,
+    // Players with a save in progress, along with the next snapshot to save
+    // once it finishes, if a newer one came in meanwhile.
+    saves: Arc<DashMap<PlayerId, Option<Player>>>,
     tx_game: Sender<Event>,
+    tx_logger: Sender<Action>,
     rx: Receiver<Event>,
+    stats: Arc<BrokerStats>,
+}
+
+/// Counts the things the broker had to give up on. None of these stop the
+/// broker, but they are worth keeping an eye on.
+#[derive(Debug, Default)]
+pub struct BrokerStats {
+    // Events for peers which had disconnected, or were link-dead.
+    pub dropped: AtomicU64,
+    // Events dropped because a peer's queue was full.
+    pub overflowed: AtomicU64,
+    // Peers disconnected because their queue was full.
+    pub slow_consumers: AtomicU64,
+    // Player saves which failed on every attempt.
+    pub failed_saves: AtomicU64,
+    // How many times the supervisor has restarted the broker loop.
+    pub restarts: AtomicU64,
 }
 
 pub type BrokerHandle = Arc<Broker>;
@@ -25,20 +67,24 @@ impl Broker {
         pg: PgPool,
         rx: Receiver<Event>,
         tx_game: Sender<Event>,
+        tx_logger: Sender<Action>,
     ) -> Result<BrokerHandle> {
         let broker = Broker {
             pg,
             tx_peers: DashMap::new(),
+            saves: Arc::new(DashMap::new()),
             tx_game,
+            tx_logger,
             rx,
+            stats: Arc::new(BrokerStats::default()),
         };
 
         let handle = Arc::new(broker);
 
-        // We create a new handle for the broker loop and move it in
+        // We create a new handle for the broker supervisor and move it in
         let loop_handle = Arc::clone(&handle);
         tokio::spawn(async move {
-            let _ = loop_handle.broker_loop().await;
+            loop_handle.supervise().await;
         });
 
         // Then we can return the original handle so the connection manager can
@@ -46,24 +92,49 @@ impl Broker {
         Ok(handle)
     }
 
-    async fn broker_loop(&self) -> Result<()> {
+    pub fn stats(&self) -> &BrokerStats {
+        &self.stats
+    }
+
+    /// Runs the broker loop, restarting it if it fails or panics. Peers are
+    /// kept across restarts, so connected players only lose the event that
+    /// caused the failure. This only returns once every sender is gone.
+    async fn supervise(self: &Arc<Self>) {
         loop {
-            tokio::select! {
-                Ok(event) = self.rx.recv_async() => {
-                    tracing::trace!("Received event: {:?}", event);
-
-                    match event {
-                        Event::Client(id, event_type) => self.handle_client_event(id, event_type).await?,
-                        Event::Game(id, event_type) => self.handle_game_event(id, event_type).await?,
-                    }
+            let result = std::panic::AssertUnwindSafe(self.broker_loop())
+                .catch_unwind()
+                .await;
+
+            match result {
+                Ok(Ok(())) => {
+                    tracing::info!("Broker channel closed; stopping the broker");
+                    return;
                 }
+                Ok(Err(e)) => tracing::error!(%e, "Broker loop failed; restarting"),
+                Err(_) => tracing::error!("Broker loop panicked; restarting"),
+            }
+
+            self.stats.restarts.fetch_add(1, Ordering::Relaxed);
+            tokio::time::sleep(RESTART_DELAY).await;
+        }
+    }
+
+    async fn broker_loop(self: &Arc<Self>) -> Result<()> {
+        while let Ok(event) = self.rx.recv_async().await {
+            tracing::trace!("Received event: {:?}", event);
+
+            match event {
+                Event::Client(id, event_type) => self.handle_client_event(id, event_type).await?,
+                Event::Game(id, event_type) => self.handle_game_event(id, event_type).await,
             }
         }
+
+        Ok(())
     }
 
     /// Processes client events from the connection pool and passes them to the
     /// game thread.
-    async fn handle_client_event(&self, id: i32, event_type: ClientEvent) -> Result<()> {
+    async fn handle_client_event(self: &Arc<Self>, id: i32, event_type: ClientEvent) -> Result<()> {
         tracing::trace!("Handling client event: {:?}", event_type);
 
         match event_type {
@@ -72,9 +143,19 @@ impl Broker {
                 // Some value. However, because the game should NOT know about
                 // the peer connection or have its send channel, we need to make
                 // this an option so we can forward as a None.
-                self.tx_peers
-                    .insert(id, tx.expect("This should never happen."));
+                let tx = tx.expect("This should never happen.");
+
+                // If the player is already connected, the new connection takes
+                // over and the old one is told to close.
+                if let Some(old) = self.tx_peers.insert(id, tx) {
+                    let _ = old.send(Event::Game(id, GameEvent::Replaced));
+                }
+
                 self.to_game(id, ClientEvent::Connect(player, None)).await?;
+
+                // Tells sent while the player was offline follow them in.
+                let broker = Arc::clone(self);
+                tokio::spawn(async move { broker.pending_tells(id).await });
             }
             ClientEvent::Command(msg) => {
                 self.to_game(id, ClientEvent::Command(msg)).await?;
@@ -82,43 +163,267 @@ impl Broker {
             ClientEvent::Ping => {
                 self.to_game(id, ClientEvent::Ping).await?;
             }
-            ClientEvent::Disconnect => {
-                self.to_game(id, ClientEvent::Disconnect).await?;
-                self.tx_peers.remove(&id);
+            ClientEvent::Tells(tells) => {
+                self.to_game(id, ClientEvent::Tells(tells)).await?;
+            }
+            ClientEvent::LineInput(purpose, text) => {
+                self.to_game(id, ClientEvent::LineInput(purpose, text))
+                    .await?;
+            }
+            ClientEvent::Ignores(ignores) => {
+                self.to_game(id, ClientEvent::Ignores(ignores)).await?;
+            }
+            ClientEvent::Banned(banned) => {
+                self.to_game(id, ClientEvent::Banned(banned)).await?;
+            }
+            ClientEvent::Disconnect(tx) => {
+                if self.remove_peer(id, tx.as_ref()) {
+                    self.to_game(id, ClientEvent::Disconnect(None)).await?;
+                }
+            }
+            ClientEvent::LinkDead(tx) => {
+                if self.remove_peer(id, tx.as_ref()) {
+                    self.to_game(id, ClientEvent::LinkDead(None)).await?;
+                }
             }
         }
         Ok(())
     }
 
     /// Processes game events from the game thread and invokes the correct
-    /// passing function.
-    async fn handle_game_event(&self, id: PlayerId, event_type: GameEvent) -> Result<()> {
+    /// passing function. Failing to deliver to one peer never affects the
+    /// others, so nothing here can fail. Requests which need the database are
+    /// carried out in the background, so a slow query never holds up other
+    /// events.
+    async fn handle_game_event(self: &Arc<Self>, id: PlayerId, event_type: GameEvent) {
         tracing::trace!("Handling game event: {:?}", event_type);
 
         match event_type {
             GameEvent::Accepted(response) => {
-                self.to_client(id, GameEvent::Accepted(response)).await?;
+                self.to_client(id, GameEvent::Accepted(response)).await;
             }
             GameEvent::Command(response) => match &response {
-                Response::Channel(here, _) => {
+                Response::Channel(here, _) | Response::Chat(here, _) => {
                     self.broadcast(here.clone(), GameEvent::Command(response))
-                        .await?;
+                        .await;
                 }
-                _ => self.to_client(id, GameEvent::Command(response)).await?,
+                _ => self.to_client(id, GameEvent::Command(response)).await,
             },
             GameEvent::Pong(response) => {
-                self.to_client(id, GameEvent::Pong(response)).await?;
+                self.to_client(id, GameEvent::Pong(response)).await;
+            }
+            GameEvent::Prompt(prompt) => {
+                self.to_client(id, GameEvent::Prompt(prompt)).await;
             }
-            GameEvent::Save(mut player) => {
-                player.save(self.pg.clone()).await;
+            GameEvent::Batch(events) => {
+                self.to_client(id, GameEvent::Batch(events)).await;
             }
+            GameEvent::Gmcp(package) => {
+                self.to_client(id, GameEvent::Gmcp(package)).await;
+            }
+            GameEvent::WrapWidth(width) => {
+                self.to_client(id, GameEvent::WrapWidth(width)).await;
+            }
+            GameEvent::Replaced => {
+                self.to_client(id, GameEvent::Replaced).await;
+            }
+            GameEvent::LineInput(purpose) => {
+                self.to_client(id, GameEvent::LineInput(purpose)).await;
+            }
+            GameEvent::Ban(moderator, request) => {
+                let broker = Arc::clone(self);
+                tokio::spawn(async move { broker.ban(id, moderator, request).await });
+            }
+            GameEvent::OfflineTell(recipient, message, suggestion) => {
+                let broker = Arc::clone(self);
+                tokio::spawn(async move {
+                    broker
+                        .offline_tell(id, &recipient, &message, suggestion.as_deref())
+                        .await
+                });
+            }
+            GameEvent::Mail(request) => {
+                let broker = Arc::clone(self);
+                tokio::spawn(async move { broker.mail(id, request).await });
+            }
+            GameEvent::Ignore(account, request) => {
+                let broker = Arc::clone(self);
+                tokio::spawn(async move { broker.ignore(id, account, request).await });
+            }
+            GameEvent::PeerReport(players) => {
+                let reply = self.peer_report(&players);
+                self.to_client(id, GameEvent::Command(Response::client_message(reply)))
+                    .await;
+            }
+            GameEvent::Save(player) => self.save(player),
             GameEvent::GlobalSave(players) => {
-                for mut player in players {
-                    player.save(self.pg.clone()).await;
+                for player in players {
+                    self.save(player);
                 }
             }
         }
-        Ok(())
+    }
+
+    /// Passes on any tells which were stored while a player was offline.
+    async fn pending_tells(&self, id: PlayerId) {
+        match tells::take_pending(id, &self.pg).await {
+            Ok(tells) if !tells.is_empty() => {
+                if let Err(e) = self.to_game(id, ClientEvent::Tells(tells)).await {
+                    tracing::error!(%e, "Failed to pass tells to game");
+                }
+            }
+            Ok(_) => {}
+            Err(e) => tracing::error!(%e, "Failed to load tells for player {}", id),
+        }
+    }
+
+    /// Carries out a moderator's ban request and answers them.
+    async fn ban(&self, id: PlayerId, moderator: Moderator, request: BanRequest) {
+        let outcome = bans::handle(request, moderator, &self.pg, &self.tx_logger).await;
+
+        // Anyone the ban covers is only kicked once it is stored, so they can't
+        // get straight back in.
+        if let Some(banned) = outcome.banned {
+            if let Err(e) = self.to_game(id, ClientEvent::Banned(banned)).await {
+                tracing::error!(%e, "Failed to pass ban to game");
+            }
+        }
+
+        self.to_client(
+            id,
+            GameEvent::Command(Response::client_message(outcome.reply)),
+        )
+        .await;
+    }
+
+    /// Stores a tell for a player who is offline, unless they are ignoring the
+    /// sender, and tells the sender how it went.
+    async fn offline_tell(
+        &self,
+        id: PlayerId,
+        recipient: &str,
+        message: &str,
+        suggestion: Option<&str>,
+    ) {
+        let reply = match ignores::is_ignoring(recipient, id, &self.pg).await {
+            Ok(false) => self.store_tell(id, recipient, message, suggestion).await,
+            Ok(true) => format!("{recipient} is not accepting tells from you."),
+            Err(e) => {
+                tracing::error!(%e, "Failed to check ignores for {}", recipient);
+                "Something went wrong; your message was not sent.".to_string()
+            }
+        };
+
+        self.to_client(id, GameEvent::Command(Response::client_message(reply)))
+            .await;
+    }
+
+    /// Carries out a request about a player's mailbox, and lets the recipient
+    /// of any new mail know about it.
+    async fn mail(&self, id: PlayerId, request: MailRequest) {
+        let outcome = mail::handle(request, id, &self.pg).await;
+        self.to_client(
+            id,
+            GameEvent::Command(Response::client_message(outcome.reply)),
+        )
+        .await;
+
+        if let Some(recipient) = outcome.notify {
+            let notice = "You have new mail. Type `mail` to read it.";
+            self.to_client(
+                recipient,
+                GameEvent::Command(Response::client_message(notice)),
+            )
+            .await;
+        }
+
+        if let Some(purpose) = outcome.write {
+            self.to_client(id, GameEvent::LineInput(purpose)).await;
+        }
+    }
+
+    /// Changes who an account is ignoring, and passes the new list on to the
+    /// game.
+    async fn ignore(&self, id: PlayerId, account: i32, request: IgnoreRequest) {
+        let outcome = ignores::handle(request, account, &self.pg).await;
+
+        if let Some(ignores) = outcome.ignores {
+            if let Err(e) = self.to_game(id, ClientEvent::Ignores(ignores)).await {
+                tracing::error!(%e, "Failed to pass ignore list to game");
+            }
+        }
+
+        self.to_client(
+            id,
+            GameEvent::Command(Response::client_message(outcome.reply)),
+        )
+        .await;
+    }
+
+    /// Stores a tell for a player who is offline, and returns what to tell the
+    /// sender.
+    async fn store_tell(
+        &self,
+        id: PlayerId,
+        recipient: &str,
+        message: &str,
+        suggestion: Option<&str>,
+    ) -> String {
+        match tells::store(id, recipient, message, &self.pg).await {
+            Ok(Some(name)) => format!(
+                "{}\n{name} is offline, and will get your message when they next log in.",
+                tells::sent(&name, message)
+            ),
+            Ok(None) => match suggestion {
+                Some(name) => format!("There is no player named {recipient}. Did you mean {name}?"),
+                None => format!("There is no player named {recipient}."),
+            },
+            Err(e) => {
+                tracing::error!(%e, "Failed to store tell for {}", recipient);
+                "Something went wrong; your message was not sent.".to_string()
+            }
+        }
+    }
+
+    /// Saves a player in the background, retrying a few times so a brief
+    /// database outage doesn't lose their progress. Saves run separately from
+    /// the broker loop, so a slow database never holds up other events.
+    ///
+    /// Each player has at most one save running at a time, so an older
+    /// snapshot can never overwrite a newer one. A snapshot which arrives
+    /// while a save is running replaces any other waiting behind it.
+    fn save(&self, player: Player) {
+        match self.saves.entry(player.id) {
+            Entry::Occupied(mut waiting) => {
+                waiting.insert(Some(player));
+            }
+            Entry::Vacant(idle) => {
+                idle.insert(None);
+
+                let pg = self.pg.clone();
+                let saves = Arc::clone(&self.saves);
+                let stats = Arc::clone(&self.stats);
+                tokio::spawn(async move {
+                    save_in_order(player, pg, saves, stats).await;
+                });
+            }
+        }
+    }
+
+    /// Removes the queue of a connection which closed. Returns false if the
+    /// player has been taken over by another connection since, in which case
+    /// the closed connection no longer speaks for them and the game isn't told.
+    fn remove_peer(&self, id: PlayerId, tx: Option<&PeerSender>) -> bool {
+        let Some(tx) = tx else {
+            self.tx_peers.remove(&id);
+            return true;
+        };
+
+        self.tx_peers
+            .remove_if(&id, |_, current| current.same_queue(tx));
+
+        // Whatever is left belongs to a newer connection.
+        !self.tx_peers.contains_key(&id)
     }
 
     /// Passes a client event to the game thread with their ID.
@@ -128,27 +433,133 @@ impl Broker {
         Ok(())
     }
 
-    /// Passes a game event to a specific client by ID.
-    async fn to_client(&self, id: PlayerId, event: GameEvent) -> Result<()> {
+    /// Passes a game event to a specific client by ID. Players who are
+    /// link-dead have no peer, so anything sent to them is dropped. Peers whose
+    /// connection has gone away without telling us, or which were disconnected
+    /// for falling behind, are removed.
+    async fn to_client(&self, id: PlayerId, event: GameEvent) {
+        let Some(tx) = self.tx_peers.get(&id).map(|tx| tx.clone()) else {
+            tracing::trace!("Dropping event for {}: peer does not exist", id);
+            self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+
+        match tx.send(Event::Game(id, event)) {
+            Delivery::Queued => return,
+            Delivery::DroppedOldest | Delivery::DroppedNew => {
+                tracing::debug!("Dropping output for {}: queue is full", id);
+                self.stats.overflowed.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+            Delivery::Disconnected => {
+                tracing::info!("Disconnecting {}: queue is full", id);
+                self.stats.slow_consumers.fetch_add(1, Ordering::Relaxed);
+            }
+            Delivery::Closed => {
+                tracing::debug!("Dropping event for {}: peer has disconnected", id);
+                self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        // Only remove the peer if it hasn't been replaced in the meantime.
         self.tx_peers
-            .get(&id)
-            .ok_or(Error {
-                kind: ErrorType::Internal,
-                message: "Peer does not exist.".to_string(),
-            })?
-            .send_async(Event::Game(id, event))
-            .await?;
+            .remove_if(&id, |_, current| current.same_queue(&tx));
+    }
 
-        Ok(())
+    /// Describes how much output is waiting for each of the players, along
+    /// with the broker's totals.
+    fn peer_report(&self, players: &[(PlayerId, String)]) -> String {
+        let mut lines = vec![format!(
+            "{:<16} {:>8} {:>8} {:>8}",
+            "Player", "Queued", "Capacity", "Dropped"
+        )];
+
+        for (id, name) in players {
+            let line = match self.tx_peers.get(id) {
+                Some(tx) => format!(
+                    "{:<16} {:>8} {:>8} {:>8}",
+                    name,
+                    tx.len(),
+                    tx.capacity(),
+                    tx.dropped()
+                ),
+                None => format!("{name:<16} {:>8}", "link-dead"),
+            };
+            lines.push(line);
+        }
+
+        lines.push(format!(
+            "\nDropped: {}  Overflowed: {}  Slow consumers: {}  Failed saves: {}  Restarts: {}",
+            self.stats.dropped.load(Ordering::Relaxed),
+            self.stats.overflowed.load(Ordering::Relaxed),
+            self.stats.slow_consumers.load(Ordering::Relaxed),
+            self.stats.failed_saves.load(Ordering::Relaxed),
+            self.stats.restarts.load(Ordering::Relaxed),
+        ));
+
+        lines.join("\n")
     }
 
     /// Passes a game event to a group of clients represented as an array of
     /// IDs.
-    async fn broadcast(&self, ids: Vec<PlayerId>, event: GameEvent) -> Result<()> {
+    async fn broadcast(&self, ids: Vec<PlayerId>, event: GameEvent) {
         for id in ids {
-            self.to_client(id, event.clone()).await?;
+            self.to_client(id, event.clone()).await;
         }
+    }
+}
 
-        Ok(())
+/// Saves a player's snapshots one after another until none are waiting. A
+/// failed save isn't retried once a newer snapshot is waiting, as that one
+/// supersedes it.
+async fn save_in_order(
+    mut player: Player,
+    pg: PgPool,
+    saves: Arc<DashMap<PlayerId, Option<Player>>>,
+    stats: Arc<BrokerStats>,
+) {
+    let id = player.id;
+    let has_newer = || saves.get(&id).is_some_and(|waiting| waiting.is_some());
+
+    loop {
+        for attempt in 1..=SAVE_ATTEMPTS {
+            match player.save(pg.clone()).await {
+                Ok(()) => break,
+                Err(e) => tracing::warn!(
+                    %e,
+                    "Failed to save player {} (attempt {}/{})",
+                    id,
+                    attempt,
+                    SAVE_ATTEMPTS
+                ),
+            }
+
+            if has_newer() {
+                tracing::info!("Dropping a failed save of player {} for a newer one", id);
+                break;
+            }
+
+            if attempt < SAVE_ATTEMPTS {
+                tokio::time::sleep(SAVE_RETRY_DELAY).await;
+            } else {
+                stats.failed_saves.fetch_add(1, Ordering::Relaxed);
+                tracing::error!("Giving up on saving player {}", id);
+            }
+        }
+
+        // Stop if nothing came in meanwhile. Otherwise the entry stays, so
+        // further snapshots keep queueing behind the one taken here.
+        if saves
+            .remove_if(&id, |_, waiting| waiting.is_none())
+            .is_some()
+        {
+            return;
+        }
+
+        let Some(next) = saves.get_mut(&id).and_then(|mut waiting| waiting.take()) else {
+            saves.remove(&id);
+            return;
+        };
+        player = next;
     }
 }
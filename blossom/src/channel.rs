@@ -0,0 +1,283 @@
+use std::collections::VecDeque;
+
+use iridescent::Styled;
+use serde::{Deserialize, Serialize};
+
+use crate::{account::Account, role::Role, theme, utils::capitalize};
+
+// Words the `channel` command uses for its subcommands, which can't be used as
+// channel names.
+const RESERVED: [&str; 4] = ["list", "join", "leave", "history"];
+
+/// A chat channel, as defined in the config file or a `channels` script.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ChannelDefinition {
+    pub name: String,
+    pub description: String,
+    // The name of a color in `theme`, such as "green".
+    pub color: String,
+    // The roles which can use the channel. Anyone can use a channel without
+    // roles.
+    pub roles: Vec<String>,
+    // Whether new players are subscribed to the channel.
+    pub auto_join: bool,
+    // How many recent messages are kept for players to catch up on.
+    pub history: usize,
+}
+
+impl Default for ChannelDefinition {
+    fn default() -> Self {
+        ChannelDefinition {
+            name: String::new(),
+            description: String::new(),
+            color: "green".to_string(),
+            roles: Vec::new(),
+            auto_join: false,
+            history: 20,
+        }
+    }
+}
+
+impl ChannelDefinition {
+    /// The channels a new game starts with.
+    pub fn defaults() -> Vec<Self> {
+        vec![
+            ChannelDefinition {
+                name: "global".to_string(),
+                description: "Out-of-character chat with everyone.".to_string(),
+                auto_join: true,
+                ..Default::default()
+            },
+            ChannelDefinition {
+                name: "staff".to_string(),
+                description: "Chat between admins and moderators.".to_string(),
+                color: "red".to_string(),
+                roles: vec!["admin".to_string(), "moderator".to_string()],
+                auto_join: true,
+                history: 50,
+            },
+        ]
+    }
+}
+
+#[derive(Debug)]
+pub struct Channel {
+    pub name: String,
+    pub description: String,
+    pub color: &'static str,
+    pub roles: Vec<Role>,
+    pub auto_join: bool,
+    // Each message is kept with its sender's account, so that replaying it
+    // follows the same ignore rules as sending it did.
+    history: VecDeque<(Account, String)>,
+    history_size: usize,
+}
+
+impl Channel {
+    fn new(definition: ChannelDefinition) -> Self {
+        let name = definition.name.to_lowercase();
+
+        let color = theme::color(&definition.color).unwrap_or_else(|| {
+            tracing::warn!(
+                "Channel {} has an unknown color: {}",
+                name,
+                definition.color
+            );
+            theme::GREEN
+        });
+
+        let roles = definition
+            .roles
+            .iter()
+            .filter_map(|role| {
+                let parsed = role.parse::<Role>().ok();
+                if parsed.is_none() {
+                    tracing::warn!("Channel {} has an unknown role: {}", name, role);
+                }
+                parsed
+            })
+            .collect();
+
+        Channel {
+            name,
+            description: definition.description,
+            color,
+            roles,
+            auto_join: definition.auto_join,
+            history: VecDeque::with_capacity(definition.history),
+            history_size: definition.history,
+        }
+    }
+
+    /// Whether an account is allowed to read and send to the channel.
+    pub fn can_access(&self, account: &Account) -> bool {
+        self.roles.is_empty() || self.roles.iter().any(|role| account.roles.contains(role))
+    }
+
+    /// Formats a message sent to the channel by a player.
+    pub fn format(&self, sender: &str, message: &str) -> String {
+        let tag = format!("[{}]", capitalize(&self.name));
+
+        format!(
+            "{} {} {}, \"{}\"",
+            tag.as_str().foreground(self.color),
+            sender,
+            verb(message),
+            message,
+        )
+    }
+
+    /// Adds a message to the replay buffer, pushing out the oldest one once it
+    /// is full.
+    pub fn record(&mut self, sender: &Account, message: String) {
+        if self.history_size == 0 {
+            return;
+        }
+
+        if self.history.len() >= self.history_size {
+            self.history.pop_front();
+        }
+
+        self.history.push_back((sender.clone(), message));
+    }
+
+    /// The most recent messages and their senders, oldest first.
+    pub fn history(&self) -> impl Iterator<Item = &(Account, String)> {
+        self.history.iter()
+    }
+}
+
+/// Every chat channel in the game, in the order they were defined.
+#[derive(Debug, Default)]
+pub struct Channels {
+    channels: Vec<Channel>,
+}
+
+impl Channels {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a channel, replacing any existing channel with the same name. This
+    /// lets scripts override the channels from the config file.
+    pub fn add(&mut self, definition: ChannelDefinition) {
+        let name = definition.name.to_lowercase();
+
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            tracing::warn!("Skipping channel with invalid name: {:?}", definition.name);
+            return;
+        }
+
+        if RESERVED.contains(&name.as_str()) {
+            tracing::warn!("Skipping channel with reserved name: {}", name);
+            return;
+        }
+
+        let channel = Channel::new(definition);
+
+        match self.channels.iter_mut().find(|c| c.name == name) {
+            Some(existing) => *existing = channel,
+            None => self.channels.push(channel),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Channel> {
+        let name = name.to_lowercase();
+        self.channels.iter().find(|c| c.name == name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Channel> {
+        let name = name.to_lowercase();
+        self.channels.iter_mut().find(|c| c.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Channel> {
+        self.channels.iter()
+    }
+
+    /// The channels a new player is subscribed to.
+    pub fn defaults(&self, account: &Account) -> Vec<String> {
+        self.channels
+            .iter()
+            .filter(|c| c.auto_join && c.can_access(account))
+            .map(|c| c.name.clone())
+            .collect()
+    }
+}
+
+/// Picks the verb for a chat message based on how it ends.
+pub fn verb(message: &str) -> &'static str {
+    if message.ends_with("!!!") {
+        "screams"
+    } else if message.ends_with('!') {
+        "exclaims"
+    } else if message.ends_with('?') {
+        "asks"
+    } else {
+        "says"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channels() -> Channels {
+        let mut channels = Channels::new();
+        for definition in ChannelDefinition::defaults() {
+            channels.add(definition);
+        }
+        channels
+    }
+
+    #[test]
+    fn role_gated_defaults() {
+        let channels = channels();
+
+        let player = Account::new(1, vec![Role::Player]);
+        let moderator = Account::new(2, vec![Role::Moderator]);
+
+        assert_eq!(channels.defaults(&player), vec!["global"]);
+        assert_eq!(channels.defaults(&moderator), vec!["global", "staff"]);
+    }
+
+    #[test]
+    fn history_is_capped() {
+        let mut channels = channels();
+        channels.add(ChannelDefinition {
+            name: "Trade".to_string(),
+            history: 2,
+            ..Default::default()
+        });
+
+        let trade = channels.get_mut("trade");
+        assert!(trade.is_some());
+
+        if let Some(trade) = trade {
+            for message in ["one", "two", "three"] {
+                trade.record(&Account::default(), message.to_string());
+            }
+
+            assert_eq!(
+                trade
+                    .history()
+                    .map(|(_, message)| message)
+                    .collect::<Vec<_>>(),
+                vec!["two", "three"]
+            );
+        }
+    }
+
+    #[test]
+    fn reserved_names_are_skipped() {
+        let mut channels = channels();
+        channels.add(ChannelDefinition {
+            name: "list".to_string(),
+            ..Default::default()
+        });
+
+        assert!(channels.get("list").is_none());
+        assert_eq!(channels.iter().count(), 2);
+    }
+}
@@ -0,0 +1,319 @@
+use bytes::{BufMut, BytesMut};
+use nectar::{event::TelnetEvent, TelnetCodec};
+use tokio_util::codec::{Decoder, Encoder};
+
+// Telnet command bytes which matter when telling text apart from negotiation.
+const IAC: u8 = 255;
+const SB: u8 = 250;
+const SE: u8 = 240;
+const WILL: u8 = 251;
+const DONT: u8 = 254;
+const GA: u8 = 249;
+const EOR: u8 = 239;
+
+// CHARSET subnegotiation commands. See RFC 2066.
+pub const CHARSET_REQUEST: u8 = 1;
+pub const CHARSET_ACCEPTED: u8 = 2;
+pub const CHARSET_REJECTED: u8 = 3;
+
+/// The character sets we can talk to Telnet clients in. Everything inside the
+/// server is UTF-8; Latin-1 is converted at the edge for legacy clients.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Charset {
+    #[default]
+    Utf8,
+    Latin1,
+}
+
+impl Charset {
+    /// Every charset we support, in order of preference. This is what we offer
+    /// in a CHARSET request.
+    pub const SUPPORTED: [Charset; 2] = [Charset::Utf8, Charset::Latin1];
+
+    /// Looks up a charset by any of its common names.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_uppercase().as_str() {
+            "UTF-8" | "UTF8" => Some(Charset::Utf8),
+            "ISO-8859-1" | "ISO_8859-1" | "ISO8859-1" | "LATIN1" | "LATIN-1" => {
+                Some(Charset::Latin1)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Charset::Utf8 => "UTF-8",
+            Charset::Latin1 => "ISO-8859-1",
+        }
+    }
+
+    /// Picks the charset to use from the list in a CHARSET request, which
+    /// looks like `;UTF-8;ISO-8859-1`. The first byte is the separator.
+    pub fn choose(request: &[u8]) -> Option<Self> {
+        let (separator, names) = request.split_first()?;
+
+        names
+            .split(|b| b == separator)
+            .filter_map(|name| Charset::from_name(&String::from_utf8_lossy(name)))
+            .next()
+    }
+}
+
+impl std::fmt::Display for Charset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Where we are within the Telnet byte stream. Only bytes seen in the `Data`
+/// state are text; everything else is negotiation and is left untouched.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Scan {
+    #[default]
+    Data,
+    Iac,
+    Option,
+    Sub,
+    SubIac,
+}
+
+impl Scan {
+    fn advance(self, byte: u8) -> Self {
+        match (self, byte) {
+            (Scan::Data, IAC) => Scan::Iac,
+            (Scan::Data, _) => Scan::Data,
+            (Scan::Iac, SB) => Scan::Sub,
+            (Scan::Iac, WILL..=DONT) => Scan::Option,
+            (Scan::Iac | Scan::Option, _) => Scan::Data,
+            (Scan::Sub, IAC) => Scan::SubIac,
+            // An escaped IAC is data within the subnegotiation.
+            (Scan::SubIac, IAC) => Scan::Sub,
+            (Scan::SubIac, SE) => Scan::Data,
+            (Scan::Sub | Scan::SubIac, _) => Scan::Sub,
+        }
+    }
+}
+
+/// Wraps the Telnet codec so clients can use a charset other than UTF-8.
+///
+/// Text is converted between the client charset and UTF-8 as it passes
+/// through, before the Telnet codec decodes it and after it encodes it, so the
+/// rest of the server only ever sees UTF-8. Subnegotiation data (such as GMCP,
+/// which is always UTF-8) is passed through as-is.
+pub struct CharsetCodec {
+    inner: TelnetCodec,
+    charset: Charset,
+    scan: Scan,
+    // Input which has been converted to UTF-8 but not decoded yet.
+    buffer: BytesMut,
+}
+
+impl CharsetCodec {
+    pub fn new(max_buffer_length: usize) -> Self {
+        Self {
+            inner: TelnetCodec::new(max_buffer_length),
+            charset: Charset::default(),
+            scan: Scan::default(),
+            buffer: BytesMut::new(),
+        }
+    }
+
+    pub fn charset(&self) -> Charset {
+        self.charset
+    }
+
+    /// Switches charsets. Input which has already been received keeps the
+    /// charset it arrived in.
+    pub fn set_charset(&mut self, charset: Charset) {
+        self.charset = charset;
+        self.scan = Scan::Data;
+    }
+
+    /// Converts Latin-1 input to UTF-8. The scan state is kept between calls,
+    /// as a read can end in the middle of a Telnet command.
+    fn latin1_to_utf8(&mut self, input: &[u8]) {
+        for &byte in input {
+            match (self.scan, byte) {
+                // An IAC in text is either a command or an escaped 0xFF, so it
+                // is held back until we know which.
+                (Scan::Data, IAC) => {}
+                (Scan::Data, _) => put_char(&mut self.buffer, char::from(byte)),
+                (Scan::Iac, IAC) => {
+                    put_char(&mut self.buffer, char::from(IAC));
+                    self.scan = Scan::Data;
+                    continue;
+                }
+                (Scan::Iac, _) => {
+                    self.buffer.put_u8(IAC);
+                    self.buffer.put_u8(byte);
+                }
+                _ => self.buffer.put_u8(byte),
+            }
+
+            self.scan = self.scan.advance(byte);
+        }
+    }
+}
+
+impl Decoder for CharsetCodec {
+    type Item = TelnetEvent;
+    type Error = <TelnetCodec as Decoder>::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let input = src.split();
+
+        match self.charset {
+            Charset::Utf8 => self.buffer.extend_from_slice(&input),
+            Charset::Latin1 => self.latin1_to_utf8(&input),
+        }
+
+        self.inner.decode(&mut self.buffer)
+    }
+}
+
+impl Encoder<TelnetEvent> for CharsetCodec {
+    type Error = <TelnetCodec as Encoder<TelnetEvent>>::Error;
+
+    fn encode(&mut self, event: TelnetEvent, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        match self.charset {
+            Charset::Utf8 => self.inner.encode(event, dst),
+            Charset::Latin1 => {
+                let mut encoded = BytesMut::new();
+                self.inner.encode(event, &mut encoded)?;
+                utf8_to_latin1(&encoded, dst);
+
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Marks the end of a prompt, so clients can tell it apart from other output.
+/// The Telnet codec has no events for these commands, so we write them
+/// ourselves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PromptMark {
+    GoAhead,
+    EndOfRecord,
+}
+
+impl Encoder<PromptMark> for CharsetCodec {
+    type Error = <TelnetCodec as Encoder<TelnetEvent>>::Error;
+
+    fn encode(&mut self, mark: PromptMark, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let command = match mark {
+            PromptMark::GoAhead => GA,
+            PromptMark::EndOfRecord => EOR,
+        };
+        dst.put_slice(&[IAC, command]);
+
+        Ok(())
+    }
+}
+
+fn put_char(dst: &mut BytesMut, c: char) {
+    dst.put_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+}
+
+/// Converts encoded UTF-8 output to Latin-1. Characters which Latin-1 can't
+/// represent are replaced with a question mark.
+fn utf8_to_latin1(input: &[u8], dst: &mut BytesMut) {
+    let mut scan = Scan::Data;
+    let mut text = Vec::new();
+
+    for &byte in input {
+        // UTF-8 never contains 0xFF, so an IAC is always the start of a
+        // command.
+        if scan == Scan::Data && byte != IAC {
+            text.push(byte);
+            continue;
+        }
+
+        put_latin1(&mut text, dst);
+        dst.put_u8(byte);
+        scan = scan.advance(byte);
+    }
+
+    put_latin1(&mut text, dst);
+}
+
+fn put_latin1(text: &mut Vec<u8>, dst: &mut BytesMut) {
+    for c in String::from_utf8_lossy(text).chars() {
+        match u8::try_from(u32::from(c)) {
+            // A literal 0xFF has to be escaped so it isn't read as an IAC.
+            Ok(IAC) => dst.put_slice(&[IAC, IAC]),
+            Ok(byte) => dst.put_u8(byte),
+            Err(_) => dst.put_u8(b'?'),
+        }
+    }
+
+    text.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escaped_iac_in_subnegotiation() {
+        // IAC SB <option> IAC IAC SE IAC SE: the first SE is data, as the IAC
+        // before it is escaped.
+        let scan = [IAC, SB, 201, IAC, IAC, SE]
+            .into_iter()
+            .fold(Scan::Data, Scan::advance);
+        assert_eq!(scan, Scan::Sub);
+
+        let scan = [IAC, SE].into_iter().fold(scan, Scan::advance);
+        assert_eq!(scan, Scan::Data);
+    }
+
+    #[test]
+    fn latin1_input() {
+        let mut codec = CharsetCodec::new(1024);
+        codec.set_charset(Charset::Latin1);
+
+        // "café ÿ", with the 0xFF escaped, around a NAWS subnegotiation whose
+        // width byte must not be converted.
+        codec.latin1_to_utf8(b"caf\xe9 \xff\xff");
+        codec.latin1_to_utf8(b"\xff\xfa\x1f\x00\xe9\x00\x18\xff");
+        codec.latin1_to_utf8(b"\xf0");
+
+        let mut expected = "café ÿ".as_bytes().to_vec();
+        expected.extend_from_slice(b"\xff\xfa\x1f\x00\xe9\x00\x18\xff\xf0");
+
+        assert_eq!(codec.buffer.as_ref(), expected.as_slice());
+    }
+
+    #[test]
+    fn latin1_output() {
+        let mut dst = BytesMut::new();
+        let mut input = "naïve ÿ 日".as_bytes().to_vec();
+        input.extend_from_slice(b"\xff\xfb\x2a");
+
+        utf8_to_latin1(&input, &mut dst);
+
+        assert_eq!(dst.as_ref(), b"na\xefve \xff\xff ?\xff\xfb\x2a");
+    }
+
+    #[test]
+    fn prompt_marks() {
+        let mut codec = CharsetCodec::new(1024);
+        let mut dst = BytesMut::new();
+
+        assert!(codec.encode(PromptMark::GoAhead, &mut dst).is_ok());
+        assert!(codec.encode(PromptMark::EndOfRecord, &mut dst).is_ok());
+        assert_eq!(dst.as_ref(), &[IAC, GA, IAC, EOR]);
+    }
+
+    #[test]
+    fn choose_charset() {
+        assert_eq!(
+            Charset::choose(b";KOI8-R;iso-8859-1;UTF-8"),
+            Some(Charset::Latin1)
+        );
+        assert_eq!(Charset::choose(b" UTF-8"), Some(Charset::Utf8));
+        assert_eq!(Charset::choose(b";KOI8-R"), None);
+        assert_eq!(Charset::choose(b""), None);
+    }
+}
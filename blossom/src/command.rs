@@ -1,5 +1,6 @@
 use crate::{
     context::Context, error::Result, quickmap::QuickMapKey, response::Response, role::Role,
+    timings::Timings,
 };
 
 pub trait GameCommand {
@@ -14,6 +15,7 @@ pub trait GameCommand {
 pub struct CommandHandle {
     pub func: Box<dyn FnMut(Context) -> Result<Response> + Send + Sync + 'static>,
     pub inner: Command,
+    pub timings: Timings,
 }
 
 /// Any text command that can be invoked by the player with its key words. This
@@ -0,0 +1,95 @@
+use crate::{
+    bans::{parse_duration, BanRequest, BanTarget, Moderator},
+    command::{Command, GameCommand},
+    context::Context,
+    error::{ErrorType, Result},
+    event::GameEvent,
+    prelude::Error,
+    response::Response,
+    role::Role,
+    world::World,
+};
+
+pub struct Ban;
+
+impl GameCommand for Ban {
+    fn create() -> Command {
+        Command {
+            name: "@ban",
+            description: "Bans an IP, CIDR range or player.",
+            permissions: vec![Role::Admin, Role::Moderator],
+            ..Default::default()
+        }
+    }
+
+    fn run(ctx: Context) -> Result<Response> {
+        let binding = ctx.world.players.read();
+        let Some(player) = binding.get(&ctx.id) else {
+            return Err(Error::new(ErrorType::Internal, "Player not found."));
+        };
+        if !player.account.is_moderator() {
+            return World::unknown(player.id);
+        }
+
+        let args = ctx.args();
+        let (Some(target), Some(duration), Some(_)) = (args.first(), args.get(1), args.get(2))
+        else {
+            return Ok(Response::client_message(
+                "Ban an IP, CIDR range or player. Usage: @ban <ip|cidr|name> <30m|12h|7d|2w|perm> <reason>",
+            ));
+        };
+
+        let Some(duration) = parse_duration(duration) else {
+            return Ok(Response::client_message(
+                "Invalid duration. Use minutes, hours, days or weeks (eg. 30m, 12h, 7d, 2w), or perm.",
+            ));
+        };
+
+        let target = BanTarget::parse(target);
+        let reason = args.get(2..).unwrap_or_default().join(" ");
+
+        if target.is_too_broad() {
+            return Ok(Response::client_message(
+                "That range is too wide to ban. Use at least a /16 for IPv4, or a /32 for IPv6.",
+            ));
+        }
+
+        // Player bans are checked against staff by the broker, as the player
+        // may be offline. Ranges are checked against the staff online now.
+        if let BanTarget::Network(network) = &target {
+            if network.contains(player._addr) {
+                return Ok(Response::client_message("You can't ban your own address."));
+            }
+
+            if let Some(staff) = binding
+                .iter()
+                .find(|p| p.account.is_moderator() && network.contains(p._addr))
+            {
+                return Ok(Response::client_message(format!(
+                    "That range covers {}, who is staff.",
+                    staff.name
+                )));
+            }
+        }
+
+        // Anyone the ban covers who is online is kicked once it is stored.
+        let moderator = Moderator {
+            account_id: player.account.id,
+            addr: player._addr,
+        };
+
+        ctx.world.send_event(
+            player.id,
+            GameEvent::Ban(
+                moderator,
+                BanRequest::Add {
+                    target,
+                    duration,
+                    reason,
+                },
+            ),
+        );
+
+        Ok(Response::Empty)
+    }
+}
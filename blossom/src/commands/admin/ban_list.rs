@@ -0,0 +1,44 @@
+use crate::{
+    bans::{BanRequest, Moderator},
+    command::{Command, GameCommand},
+    context::Context,
+    error::{ErrorType, Result},
+    event::GameEvent,
+    prelude::Error,
+    response::Response,
+    role::Role,
+    world::World,
+};
+
+pub struct BanList;
+
+impl GameCommand for BanList {
+    fn create() -> Command {
+        Command {
+            name: "@bans",
+            description: "Lists all active bans.",
+            permissions: vec![Role::Admin, Role::Moderator],
+            ..Default::default()
+        }
+    }
+
+    fn run(ctx: Context) -> Result<Response> {
+        let binding = ctx.world.players.read();
+        let Some(player) = binding.get(&ctx.id) else {
+            return Err(Error::new(ErrorType::Internal, "Player not found."));
+        };
+        if !player.account.is_moderator() {
+            return World::unknown(player.id);
+        }
+
+        let moderator = Moderator {
+            account_id: player.account.id,
+            addr: player._addr,
+        };
+
+        ctx.world
+            .send_event(player.id, GameEvent::Ban(moderator, BanRequest::List));
+
+        Ok(Response::Empty)
+    }
+}
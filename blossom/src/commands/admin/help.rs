@@ -18,7 +18,13 @@ Commands:
     @version                  - show the server version
     @world                    - display world information
     @player <name>            - display information about a player
-    @system <command> <name>  - run a system command
+    @systems                  - show how long systems and commands take to run
+    @systems <command> <name> - start, stop or pause a system
+    @ban <target> <time> <reason>
+                              - ban an IP, CIDR range or player (eg. 7d, perm)
+    @unban <id>               - lift a ban
+    @bans                     - list active bans
+    @queues                   - show each client's pending output
     @shutdown                 - shutdown the server
 
 ================================================================================
@@ -1,7 +1,11 @@
+pub mod ban;
+pub mod ban_list;
 pub mod help;
 pub mod player_info;
+pub mod queues;
 pub mod room_info;
 pub mod shutdown;
 pub mod system_control;
+pub mod unban;
 pub mod version;
 pub mod world_info;
@@ -0,0 +1,45 @@
+use crate::{
+    command::{Command, GameCommand},
+    context::Context,
+    error::{ErrorType, Result},
+    event::GameEvent,
+    prelude::Error,
+    response::Response,
+    role::Role,
+    world::World,
+};
+
+pub struct Queues;
+
+impl GameCommand for Queues {
+    fn create() -> Command {
+        Command {
+            name: "@queues",
+            description: "Shows how much output is waiting for each client.",
+            permissions: vec![Role::Admin],
+            ..Default::default()
+        }
+    }
+
+    fn run(ctx: Context) -> Result<Response> {
+        let binding = ctx.world.players.read();
+        let Some(player) = binding.get(&ctx.id) else {
+            return Err(Error::new(ErrorType::Internal, "Player not found."));
+        };
+        if !player.account.roles.contains(&Role::Admin) {
+            return World::unknown(player.id);
+        }
+
+        // The queues belong to the broker, so it answers with the report.
+        let mut players = binding
+            .iter()
+            .map(|p| (p.id, p.name.clone()))
+            .collect::<Vec<_>>();
+        players.sort_by(|a, b| a.1.cmp(&b.1));
+
+        ctx.world
+            .send_event(player.id, GameEvent::PeerReport(players));
+
+        Ok(Response::Empty)
+    }
+}
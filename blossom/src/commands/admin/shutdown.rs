@@ -1,13 +1,14 @@
-use std::{thread::sleep, time::Duration};
+use std::time::Duration;
 
 use crate::{
     command::{Command, GameCommand},
     context::Context,
     error::{ErrorType, Result},
-    event::{Event, GameEvent},
+    event::GameEvent,
     prelude::Error,
     response::Response,
     role::Role,
+    world::World,
 };
 
 pub struct Shutdown;
@@ -22,58 +23,62 @@ impl GameCommand for Shutdown {
     }
 
     fn run(ctx: Context) -> Result<Response> {
-        let binding = ctx.world.players.read();
-        let Some(player) = binding.get(&ctx.id) else {
-            return Err(Error::new(ErrorType::Internal, "Player not found."));
+        let is_admin = {
+            let binding = ctx.world.players.read();
+            let Some(player) = binding.get(&ctx.id) else {
+                return Err(Error::new(ErrorType::Internal, "Player not found."));
+            };
+
+            player.account.roles.contains(&Role::Admin)
         };
 
-        if player.account.roles.contains(&Role::Admin) {
-            let players = ctx
-                .world
-                .players
-                .read()
-                .iter()
-                .map(|p| p.id)
-                .collect::<Vec<_>>();
+        if !is_admin {
+            return Ok(Response::Empty);
+        }
 
-            ctx.world.send_command(
-                player.id,
-                Response::Channel(
-                    players,
-                    "Server shutting down in 30 seconds. Please log out to save your progress."
-                        .to_string(),
-                ),
-            );
+        let players = ctx
+            .world
+            .players
+            .read()
+            .iter()
+            .map(|p| p.id)
+            .collect::<Vec<_>>();
 
-            let tx = ctx.world.broker.clone();
-            let players = ctx
-                .world
-                .players
-                .read()
-                .iter()
-                .filter(|p| p.dirty)
-                .cloned()
-                .collect();
+        ctx.world.send_command(
+            ctx.id,
+            Response::Channel(
+                players,
+                "Server shutting down in 30 seconds. Please log out to save your progress."
+                    .to_string(),
+            ),
+        );
 
-            // can we return from a task?
-            tokio::spawn(async move {
-                sleep(Duration::from_secs(30));
+        ctx.world
+            .after("shutdown", Duration::from_secs(30), save_and_exit);
 
-                tracing::info!("Running global save...");
-                let _ = tx
-                    .send_async(Event::Game(
-                        -1, // this ID doesn't actually matter
-                        GameEvent::GlobalSave(players),
-                    ))
-                    .await;
-                tracing::info!("Global save complete.");
+        Ok(Response::Empty)
+    }
+}
 
-                sleep(Duration::from_secs(10));
+/// Saves everyone, then exits once the saves have had time to finish.
+fn save_and_exit(world: &mut World) {
+    tracing::info!("Running global save...");
 
-                std::process::exit(0);
-            });
-        }
+    // Players are collected now rather than when the countdown started, so
+    // progress made in the meantime is saved too.
+    let players = world
+        .players
+        .read()
+        .iter()
+        .filter(|p| p.dirty)
+        .cloned()
+        .collect();
+    world.send_event(
+        -1, // this ID doesn't actually matter
+        GameEvent::GlobalSave(players),
+    );
 
-        Ok(Response::Empty)
-    }
+    // Saves run in the background, so they are given time to finish before
+    // the process exits.
+    world.after("exit", Duration::from_secs(10), |_| std::process::exit(0));
 }
@@ -90,7 +90,11 @@ impl GameCommand for SystemsControl {
                     "restart" => Ok(Response::client_message("Not implemented.")),
                     _ => Ok(Response::client_message("Invalid system command. Options are ['start', 'stop', 'pause', 'restart'].")),
                 },
-                None => Ok(Response::client_message("Various system control commands.")),
+                None => Ok(Response::client_message(format!(
+                    "System Timings:{}\nCommand Timings:{}",
+                    ctx.world.systems.timings(),
+                    ctx.world.command_timings()
+                ))),
             }
         } else {
             World::unknown(player.id)
@@ -0,0 +1,54 @@
+use crate::{
+    bans::{BanRequest, Moderator},
+    command::{Command, GameCommand},
+    context::Context,
+    error::{ErrorType, Result},
+    event::GameEvent,
+    prelude::Error,
+    response::Response,
+    role::Role,
+    world::World,
+};
+
+pub struct Unban;
+
+impl GameCommand for Unban {
+    fn create() -> Command {
+        Command {
+            name: "@unban",
+            description: "Lifts a ban.",
+            permissions: vec![Role::Admin, Role::Moderator],
+            ..Default::default()
+        }
+    }
+
+    fn run(ctx: Context) -> Result<Response> {
+        let binding = ctx.world.players.read();
+        let Some(player) = binding.get(&ctx.id) else {
+            return Err(Error::new(ErrorType::Internal, "Player not found."));
+        };
+        if !player.account.is_moderator() {
+            return World::unknown(player.id);
+        }
+
+        let Some(id) = ctx
+            .args()
+            .first()
+            .and_then(|id| id.trim_start_matches('#').parse::<i32>().ok())
+        else {
+            return Ok(Response::client_message(
+                "Lift a ban. Usage: @unban <id> (see @bans for IDs)",
+            ));
+        };
+
+        let moderator = Moderator {
+            account_id: player.account.id,
+            addr: player._addr,
+        };
+
+        ctx.world
+            .send_event(player.id, GameEvent::Ban(moderator, BanRequest::Lift(id)));
+
+        Ok(Response::Empty)
+    }
+}
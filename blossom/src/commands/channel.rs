@@ -0,0 +1,214 @@
+use crate::{
+    command::{Command, GameCommand},
+    context::Context,
+    error::{ErrorType, Result},
+    player::PlayerId,
+    prelude::Error,
+    response::{Chat, Response},
+    world::World,
+};
+
+const USAGE: &str =
+    "Usage: channel [list] | channel <join|leave|history> <name> | channel <name> <message>";
+
+pub struct ChannelCommand;
+
+impl GameCommand for ChannelCommand {
+    fn create() -> Command {
+        Command {
+            name: "channel",
+            arguments: vec!["name", "message"],
+            description: "Lists, joins and leaves chat channels, and sends messages to them.",
+            aliases: vec!["channels", "chan"],
+            ..Default::default()
+        }
+    }
+
+    fn run(ctx: Context) -> Result<Response> {
+        let subcommand = ctx.args().first().map(|arg| arg.to_lowercase());
+        let name = ctx.args().get(1).cloned().unwrap_or_default();
+
+        match subcommand.as_deref() {
+            None | Some("list") => list(ctx.world, ctx.id),
+            Some("join") => join(ctx.world, ctx.id, &name),
+            Some("leave") => leave(ctx.world, ctx.id, &name),
+            Some("history") => history(ctx.world, ctx.id, &name),
+            Some(channel) => {
+                let message = ctx.args().get(1..).unwrap_or_default().join(" ");
+                speak(ctx.world, ctx.id, channel, &message)
+            }
+        }
+    }
+}
+
+/// Sends a message to a channel the player is subscribed to. Everyone else
+/// subscribed to the channel receives it, and it is kept in the channel's
+/// history.
+pub fn speak(world: &mut World, id: PlayerId, name: &str, message: &str) -> Result<Response> {
+    let binding = world.players.read();
+    let Some(player) = binding.get(&id) else {
+        return Err(Error::new(ErrorType::Internal, "Player not found."));
+    };
+
+    let Some(channel) = world
+        .channels
+        .get_mut(name)
+        .filter(|c| c.can_access(&player.account))
+    else {
+        return Ok(Response::client_message(format!(
+            "There is no channel named {name}."
+        )));
+    };
+
+    if !player.in_channel(&channel.name) {
+        return Ok(Response::client_message(format!(
+            "You are not in the {0} channel. Type `channel join {0}` to join it.",
+            channel.name
+        )));
+    }
+
+    if message.is_empty() {
+        return Ok(Response::client_message(USAGE));
+    }
+
+    let text = channel.format(&player.name, message);
+    channel.record(&player.account, text.clone());
+
+    let recipients = binding
+        .iter()
+        .filter(|p| {
+            p.in_channel(&channel.name)
+                && channel.can_access(&p.account)
+                && !p.is_ignoring(&player.account)
+        })
+        .map(|p| p.id)
+        .collect::<Vec<_>>();
+
+    Ok(Response::Chat(
+        recipients,
+        Chat {
+            channel: channel.name.clone(),
+            sender: player.id,
+            name: player.name.clone(),
+            text,
+        },
+    ))
+}
+
+fn list(world: &World, id: PlayerId) -> Result<Response> {
+    let binding = world.players.read();
+    let Some(player) = binding.get(&id) else {
+        return Err(Error::new(ErrorType::Internal, "Player not found."));
+    };
+
+    let mut lines = vec!["Channels (* joined):".to_string()];
+    lines.extend(
+        world
+            .channels
+            .iter()
+            .filter(|c| c.can_access(&player.account))
+            .map(|c| {
+                let marker = if player.in_channel(&c.name) { "*" } else { " " };
+                format!("  {marker} {:<12} {}", c.name, c.description)
+            }),
+    );
+    lines.push(format!("\n{USAGE}"));
+
+    Ok(Response::client_message(lines.join("\n")))
+}
+
+fn join(world: &World, id: PlayerId, name: &str) -> Result<Response> {
+    let mut binding = world.players.write();
+    let Some(player) = binding.get_mut(&id) else {
+        return Err(Error::new(ErrorType::Internal, "Player not found."));
+    };
+
+    let Some(channel) = world
+        .channels
+        .get(name)
+        .filter(|c| c.can_access(&player.account))
+    else {
+        return Ok(Response::client_message(format!(
+            "There is no channel named {name}."
+        )));
+    };
+
+    if player.in_channel(&channel.name) {
+        return Ok(Response::client_message(format!(
+            "You are already in the {} channel.",
+            channel.name
+        )));
+    }
+
+    player
+        .channels
+        .get_or_insert_with(Vec::new)
+        .push(channel.name.clone());
+    player.dirty = true;
+
+    Ok(Response::client_message(format!(
+        "You join the {0} channel. Type `channel history {0}` to catch up.",
+        channel.name
+    )))
+}
+
+fn leave(world: &World, id: PlayerId, name: &str) -> Result<Response> {
+    let mut binding = world.players.write();
+    let Some(player) = binding.get_mut(&id) else {
+        return Err(Error::new(ErrorType::Internal, "Player not found."));
+    };
+
+    let name = name.to_lowercase();
+    if !player.in_channel(&name) {
+        return Ok(Response::client_message(format!(
+            "You are not in the {name} channel."
+        )));
+    }
+
+    if let Some(channels) = player.channels.as_mut() {
+        channels.retain(|channel| *channel != name);
+    }
+    player.dirty = true;
+
+    Ok(Response::client_message(format!(
+        "You leave the {name} channel."
+    )))
+}
+
+fn history(world: &World, id: PlayerId, name: &str) -> Result<Response> {
+    let binding = world.players.read();
+    let Some(player) = binding.get(&id) else {
+        return Err(Error::new(ErrorType::Internal, "Player not found."));
+    };
+
+    let Some(channel) = world
+        .channels
+        .get(name)
+        .filter(|c| c.can_access(&player.account))
+    else {
+        return Ok(Response::client_message(format!(
+            "There is no channel named {name}."
+        )));
+    };
+
+    // Messages from anyone the player ignores are left out, as they were when
+    // the messages were sent.
+    let mut lines = channel
+        .history()
+        .filter(|(sender, _)| !player.is_ignoring(sender))
+        .map(|(_, message)| message.clone())
+        .peekable();
+    if lines.peek().is_none() {
+        return Ok(Response::client_message(format!(
+            "There are no recent messages on the {} channel.",
+            channel.name
+        )));
+    }
+
+    Ok(Response::client_message(
+        std::iter::once(format!("Recent messages on the {} channel:", channel.name))
+            .chain(lines)
+            .collect::<Vec<_>>()
+            .join("\n"),
+    ))
+}
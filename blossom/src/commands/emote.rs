@@ -0,0 +1,49 @@
+use crate::{
+    command::{Command, GameCommand},
+    context::Context,
+    error::{ErrorType, Result},
+    prelude::Error,
+    response::{Chat, Response},
+};
+
+pub struct Emote;
+
+impl GameCommand for Emote {
+    fn create() -> Command {
+        Command {
+            name: "emote",
+            description: "Shows everyone in the room what your character is doing.",
+            aliases: vec!["me"],
+            ..Default::default()
+        }
+    }
+
+    fn run(ctx: Context) -> Result<Response> {
+        let action = ctx.input.args.join(" ");
+
+        if action.is_empty() {
+            return Ok(Response::client_message("Usage: emote <action>"));
+        }
+
+        let binding = ctx.world.players.read();
+        let Some(player) = binding.get(&ctx.id) else {
+            return Err(Error::new(ErrorType::Internal, "Player not found."));
+        };
+
+        let players_in_room = binding
+            .iter()
+            .filter(|p| p.position == player.position && !p.is_ignoring(&player.account))
+            .map(|p| p.id)
+            .collect::<Vec<_>>();
+
+        Ok(Response::Chat(
+            players_in_room,
+            Chat {
+                channel: "emote".to_string(),
+                sender: player.id,
+                name: player.name.clone(),
+                text: format!("{} {action}", player.name),
+            },
+        ))
+    }
+}
@@ -15,10 +15,25 @@ Commands:
     look, l                 - display the current rooms description
     n, e, s, w, u, d        - move north, east, south, west, up, down respectively
     say, ,                  - say something in local (room) chat
+    emote, me               - show the room what you are doing
     ooc, global             - say something in global (world) chat
+    channel, chan           - list, join or leave chat channels
+    chan <name> <message>   - say something on a chat channel
+    chan history <name>     - show recent messages on a chat channel
+    tell <name> <message>   - send a private message, even to offline players
+    reply <message>         - reply to the last player who sent you a tell
+    mail                    - list your mail
+    mail read <id>          - read a mail
+    mail send <name> <subject>
+                            - write a mail to another player
+    mail reply <id>         - reply to a mail
+    mail delete <id>        - delete a mail
+    ignore [name]           - ignore a player, or list who you are ignoring
+    unignore <name>         - stop ignoring a player
     who                     - list all online players
     afk                     - toggle AFK mode
     brief                   - toggle brief mode
+    wrap <width|off>        - set the width text is wrapped at
     exit, logout, quit      - quit the game
 
 ================================================================================
@@ -0,0 +1,62 @@
+use crate::{
+    command::{Command, GameCommand},
+    context::Context,
+    error::{ErrorType, Result},
+    event::GameEvent,
+    ignores::IgnoreRequest,
+    prelude::Error,
+    response::Response,
+    utils::normalize_name,
+};
+
+pub struct Ignore;
+
+impl GameCommand for Ignore {
+    fn create() -> Command {
+        Command {
+            name: "ignore",
+            arguments: vec!["name"],
+            description: "Hides messages from another player, or lists who you are ignoring.",
+            ..Default::default()
+        }
+    }
+
+    fn run(ctx: Context) -> Result<Response> {
+        let binding = ctx.world.players.read();
+        let Some(player) = binding.get(&ctx.id) else {
+            return Err(Error::new(ErrorType::Internal, "Player not found."));
+        };
+
+        let name = match ctx.args().first().map(|arg| normalize_name(arg)) {
+            None => None,
+            Some(arg) if arg == "List" => None,
+            Some(arg) => Some(arg),
+        };
+
+        let Some(name) = name else {
+            if player.ignores.is_empty() {
+                return Ok(Response::client_message("You aren't ignoring anyone."));
+            }
+
+            let names = player
+                .ignores
+                .iter()
+                .map(|i| i.name.as_str())
+                .collect::<Vec<_>>();
+
+            return Ok(Response::client_message(format!(
+                "You are ignoring: {}",
+                names.join(", ")
+            )));
+        };
+
+        // The list is stored in the database, so the broker makes the change
+        // and checks the player can be ignored.
+        ctx.world.send_event(
+            ctx.id,
+            GameEvent::Ignore(player.account.id, IgnoreRequest::Add(name)),
+        );
+
+        Ok(Response::Empty)
+    }
+}
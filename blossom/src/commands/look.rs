@@ -48,7 +48,7 @@ impl GameCommand for Look {
         });
 
         if let Some(view) = view {
-            Ok(Response::client_message(view))
+            Ok(Response::Room(view))
         } else {
             Ok(Response::client_message(
                 "You are lost in the void. There is nowhere to go.",
@@ -0,0 +1,73 @@
+use crate::{
+    command::{Command, GameCommand},
+    context::Context,
+    error::Result,
+    event::GameEvent,
+    mail::{MailRecipient, MailRequest, MAX_SUBJECT_LENGTH},
+    response::Response,
+    utils::normalize_name,
+};
+
+const USAGE: &str =
+    "Usage: mail [list] | mail <read|delete|reply> <id> | mail send <name> <subject>";
+
+pub struct MailCommand;
+
+impl GameCommand for MailCommand {
+    fn create() -> Command {
+        Command {
+            name: "mail",
+            arguments: vec!["subcommand"],
+            description: "Reads, writes and deletes your mail.",
+            ..Default::default()
+        }
+    }
+
+    fn run(ctx: Context) -> Result<Response> {
+        let args = ctx.args();
+        let subcommand = args.first().map(|arg| arg.to_lowercase());
+
+        // Mail is referred to by ID, which can be written as `12` or `#12`.
+        let id = args
+            .get(1)
+            .and_then(|arg| arg.trim_start_matches('#').parse::<i32>().ok());
+
+        let request = match (subcommand.as_deref(), id) {
+            (None | Some("list"), _) => MailRequest::List,
+            (Some("read"), Some(id)) => MailRequest::Read(id),
+            (Some("delete"), Some(id)) => MailRequest::Delete(id),
+            (Some("reply"), Some(id)) => MailRequest::Write {
+                recipient: MailRecipient::ReplyTo(id),
+                subject: String::new(),
+            },
+            (Some("send"), _) => {
+                let (Some(name), Some(subject)) = (args.get(1), args.get(2..)) else {
+                    return Ok(Response::client_message(USAGE));
+                };
+                let subject = subject.join(" ");
+
+                if subject.is_empty() {
+                    return Ok(Response::client_message(USAGE));
+                }
+
+                if subject.chars().count() > MAX_SUBJECT_LENGTH {
+                    return Ok(Response::client_message(format!(
+                        "Subjects can be up to {MAX_SUBJECT_LENGTH} characters long."
+                    )));
+                }
+
+                MailRequest::Write {
+                    recipient: MailRecipient::Player(normalize_name(name)),
+                    subject,
+                }
+            }
+            _ => return Ok(Response::client_message(USAGE)),
+        };
+
+        // The mailbox is stored in the database, so the broker answers. Mail is
+        // only written once the broker has checked who it is going to.
+        ctx.world.send_event(ctx.id, GameEvent::Mail(request));
+
+        Ok(Response::Empty)
+    }
+}
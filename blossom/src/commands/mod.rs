@@ -2,12 +2,20 @@ pub mod admin;
 pub mod afk;
 pub mod brief;
 pub mod builder;
+pub mod channel;
+pub mod emote;
 pub mod help;
+pub mod ignore;
 pub mod look;
+pub mod mail;
 pub mod moderator;
 pub mod ooc;
 pub mod quit;
+pub mod reply;
 pub mod say;
+pub mod tell;
+pub mod unignore;
 pub mod unknown;
 pub mod walk;
 pub mod who;
+pub mod wrap;
@@ -1,12 +1,9 @@
-use iridescent::Styled;
-
 use crate::{
     command::{Command, GameCommand},
+    commands::channel::speak,
     context::Context,
-    error::{ErrorType, Result},
-    prelude::Error,
+    error::Result,
     response::Response,
-    theme,
 };
 
 pub struct GlobalChat;
@@ -15,7 +12,7 @@ impl GameCommand for GlobalChat {
     fn create() -> Command {
         Command {
             name: "global",
-            description: "Sends a message to all players in the game.",
+            description: "Sends a message to the global channel.",
             aliases: vec!["ooc"],
             ..Default::default()
         }
@@ -28,37 +25,6 @@ impl GameCommand for GlobalChat {
             return Ok(Response::Empty);
         }
 
-        let verb = if message.ends_with('?') {
-            "asks"
-        } else if message.ends_with('!') {
-            "exclaims"
-        } else if message.ends_with("!!!") {
-            "screams"
-        } else {
-            "says"
-        };
-
-        let binding = ctx.world.players.read();
-        let Some(player) = binding.get(&ctx.id) else {
-            return Err(Error::new(ErrorType::Internal, "Player not found."));
-        };
-
-        let msg = format!(
-            "{} {} {}, \"{}\"",
-            "[Global]".foreground(theme::GREEN),
-            player.name,
-            verb,
-            message,
-        );
-
-        let all = ctx
-            .world
-            .players
-            .read()
-            .iter()
-            .map(|p| p.id)
-            .collect::<Vec<_>>();
-
-        Ok(Response::Channel(all, msg))
+        speak(ctx.world, ctx.id, "global", &message)
     }
 }
@@ -0,0 +1,49 @@
+use crate::{
+    command::{Command, GameCommand},
+    commands::tell::send,
+    context::Context,
+    error::{ErrorType, Result},
+    prelude::Error,
+    response::Response,
+};
+
+pub struct Reply;
+
+impl GameCommand for Reply {
+    fn create() -> Command {
+        Command {
+            name: "reply",
+            arguments: vec!["message"],
+            description: "Replies to the last player who sent you a tell.",
+            ..Default::default()
+        }
+    }
+
+    fn run(ctx: Context) -> Result<Response> {
+        let message = ctx.input.args.join(" ");
+
+        if message.is_empty() {
+            return Ok(Response::client_message("Usage: reply <message>"));
+        }
+
+        let (name, recipient) = {
+            let binding = ctx.world.players.read();
+            let Some(player) = binding.get(&ctx.id) else {
+                return Err(Error::new(ErrorType::Internal, "Player not found."));
+            };
+
+            let Some(name) = player.reply_to.clone() else {
+                return Ok(Response::client_message("Nobody has sent you a tell yet."));
+            };
+
+            let recipient = binding
+                .iter()
+                .find(|p| p.name == name && p.linkdead.is_none())
+                .map(|p| p.id);
+
+            (name, recipient)
+        };
+
+        send(ctx.world, ctx.id, recipient, &name, &message, None)
+    }
+}
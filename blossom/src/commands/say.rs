@@ -1,9 +1,10 @@
 use crate::{
+    channel::verb,
     command::{Command, GameCommand},
     context::Context,
     error::{ErrorType, Result},
     prelude::Error,
-    response::Response,
+    response::{Chat, Response},
 };
 
 pub struct Say;
@@ -25,16 +26,6 @@ impl GameCommand for Say {
             return Ok(Response::Empty);
         }
 
-        let verb = if message.ends_with('?') {
-            "asks"
-        } else if message.ends_with('!') {
-            "exclaims"
-        } else if message.ends_with("!!!") {
-            "screams"
-        } else {
-            "says"
-        };
-
         let binding = ctx.world.players.read();
         let Some(player) = binding.get(&ctx.id) else {
             return Err(Error::new(ErrorType::Internal, "Player not found."));
@@ -42,17 +33,22 @@ impl GameCommand for Say {
         let name = player.name.as_str();
         let position = player.position;
 
-        let msg = format!("{name} {verb}, \"{message}\"");
+        let msg = format!("{name} {}, \"{message}\"", verb(&message));
 
-        let players_in_room = ctx
-            .world
-            .players
-            .read()
+        let players_in_room = binding
             .iter()
-            .filter(|p| p.position == position)
+            .filter(|p| p.position == position && !p.is_ignoring(&player.account))
             .map(|p| p.id)
             .collect::<Vec<_>>();
 
-        Ok(Response::Channel(players_in_room, msg))
+        Ok(Response::Chat(
+            players_in_room,
+            Chat {
+                channel: "say".to_string(),
+                sender: player.id,
+                name: name.to_string(),
+                text: msg,
+            },
+        ))
     }
 }
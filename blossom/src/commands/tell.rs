@@ -0,0 +1,121 @@
+use crate::{
+    command::{Command, GameCommand},
+    context::Context,
+    error::{ErrorType, Result},
+    event::GameEvent,
+    player::PlayerId,
+    prelude::Error,
+    response::Response,
+    tells,
+    utils::normalize_name,
+    world::World,
+};
+
+pub struct Tell;
+
+impl GameCommand for Tell {
+    fn create() -> Command {
+        Command {
+            name: "tell",
+            arguments: vec!["name", "message"],
+            description: "Sends a private message to another player, even if they are offline.",
+            ..Default::default()
+        }
+    }
+
+    fn run(ctx: Context) -> Result<Response> {
+        let (Some(name), Some(message)) = (ctx.args().first(), ctx.args().get(1..)) else {
+            return Ok(Response::client_message("Usage: tell <name> <message>"));
+        };
+        let message = message.join(" ");
+
+        if message.is_empty() {
+            return Ok(Response::client_message("Usage: tell <name> <message>"));
+        }
+
+        // Only an exact name is sent to, whether or not that player is online,
+        // as a loose match could deliver the tell to the wrong person. The
+        // closest online player is only suggested if nobody has that name.
+        let (recipient, suggestion) = {
+            let binding = ctx.world.players.read();
+            let online = binding
+                .iter()
+                .filter(|p| p.linkdead.is_none())
+                .collect::<Vec<_>>();
+
+            match online
+                .iter()
+                .find(|p| p.name.to_lowercase() == name.to_lowercase())
+            {
+                Some(player) => (Some(player.id), None),
+                None => (
+                    None,
+                    ctx.input
+                        .fuzzy_match(&online[..])
+                        .and_then(|i| online.get(i))
+                        .map(|p| p.name.clone()),
+                ),
+            }
+        };
+
+        send(ctx.world, ctx.id, recipient, name, &message, suggestion)
+    }
+}
+
+/// Sends a tell to `recipient` if they are online. Otherwise, the broker
+/// stores it for the player named `name` until they next log in, or offers
+/// `suggestion` if there is no such player.
+pub fn send(
+    world: &World,
+    id: PlayerId,
+    recipient: Option<PlayerId>,
+    name: &str,
+    message: &str,
+    suggestion: Option<String>,
+) -> Result<Response> {
+    let mut binding = world.players.write();
+    let Some((sender, account)) = binding
+        .get(&id)
+        .map(|p| (p.name.clone(), p.account.clone()))
+    else {
+        return Err(Error::new(ErrorType::Internal, "Player not found."));
+    };
+
+    let Some(recipient) = recipient.and_then(|recipient| binding.get_mut(&recipient)) else {
+        world.send_event(
+            id,
+            GameEvent::OfflineTell(normalize_name(name), message.to_string(), suggestion),
+        );
+
+        return Ok(Response::Empty);
+    };
+
+    if recipient.id == id {
+        return Ok(Response::client_message(
+            "You mutter something to yourself.",
+        ));
+    }
+
+    if recipient.is_ignoring(&account) {
+        return Ok(Response::client_message(format!(
+            "{} is not accepting tells from you.",
+            recipient.name
+        )));
+    }
+
+    recipient.reply_to = Some(sender.clone());
+    world.send_command(
+        recipient.id,
+        Response::client_message(tells::received(&sender, message)),
+    );
+
+    let mut reply = tells::sent(&recipient.name, message);
+    if recipient.afk {
+        reply.push_str(&format!(
+            "\n{} is AFK, and may not see your message right away.",
+            recipient.name
+        ));
+    }
+
+    Ok(Response::client_message(reply))
+}
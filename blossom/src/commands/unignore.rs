@@ -0,0 +1,41 @@
+use crate::{
+    command::{Command, GameCommand},
+    context::Context,
+    error::{ErrorType, Result},
+    event::GameEvent,
+    ignores::IgnoreRequest,
+    prelude::Error,
+    response::Response,
+    utils::normalize_name,
+};
+
+pub struct Unignore;
+
+impl GameCommand for Unignore {
+    fn create() -> Command {
+        Command {
+            name: "unignore",
+            arguments: vec!["name"],
+            description: "Stops ignoring another player.",
+            ..Default::default()
+        }
+    }
+
+    fn run(ctx: Context) -> Result<Response> {
+        let Some(name) = ctx.args().first().map(|arg| normalize_name(arg)) else {
+            return Ok(Response::client_message("Usage: unignore <name>"));
+        };
+
+        let binding = ctx.world.players.read();
+        let Some(player) = binding.get(&ctx.id) else {
+            return Err(Error::new(ErrorType::Internal, "Player not found."));
+        };
+
+        ctx.world.send_event(
+            ctx.id,
+            GameEvent::Ignore(player.account.id, IgnoreRequest::Remove(name)),
+        );
+
+        Ok(Response::Empty)
+    }
+}
@@ -140,6 +140,6 @@ impl GameCommand for Walk {
             return Ok(Response::client_message(LOST_MESSAGE));
         };
 
-        Ok(Response::client_message(view))
+        Ok(Response::Room(view))
     }
 }
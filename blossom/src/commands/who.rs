@@ -23,7 +23,9 @@ impl GameCommand for Who {
             .read()
             .iter()
             .map(|p| {
-                if p.afk {
+                if p.linkdead.is_some() {
+                    format!("{} (link-dead)", p.name)
+                } else if p.afk {
                     format!("{} (AFK)", p.name)
                 } else {
                     p.name.clone()
@@ -0,0 +1,68 @@
+use crate::{
+    command::{Command, GameCommand},
+    context::Context,
+    error::{ErrorType, Result},
+    event::GameEvent,
+    prelude::Error,
+    response::Response,
+};
+
+pub struct Wrap;
+
+impl GameCommand for Wrap {
+    fn create() -> Command {
+        Command {
+            name: "wrap",
+            arguments: vec!["width"],
+            description: "Sets the width output is wrapped at. Only used when your client does not report its window size.",
+            ..Default::default()
+        }
+    }
+
+    fn run(ctx: Context) -> Result<Response> {
+        let width = match ctx.args().first().map(String::as_str) {
+            Some("off") => 0,
+            Some(arg) => match arg.parse::<i32>() {
+                Ok(width) if (20..=250).contains(&width) => width,
+                _ => {
+                    return Ok(Response::client_message(
+                        "Wrap width should be a number between 20 and 250, or `off`.",
+                    ))
+                }
+            },
+            None => {
+                let binding = ctx.world.players.read();
+                let Some(player) = binding.get(&ctx.id) else {
+                    return Err(Error::new(ErrorType::Internal, "Player not found."));
+                };
+
+                return Ok(Response::client_message(match player.wrap_width {
+                    0 => "Wrapping is off. Usage: wrap <width|off>".to_string(),
+                    width => format!("Wrapping at {width} characters. Usage: wrap <width|off>"),
+                }));
+            }
+        };
+
+        let mut binding = ctx.world.players.write();
+        let Some(player) = binding.get_mut(&ctx.id) else {
+            return Err(Error::new(ErrorType::Internal, "Player not found."));
+        };
+
+        player.wrap_width = width;
+        player.dirty = true;
+
+        // The connection does the actual wrapping, so it needs to know too.
+        ctx.world.send_event(
+            ctx.id,
+            GameEvent::WrapWidth(usize::try_from(width).unwrap_or_default()),
+        );
+
+        if width == 0 {
+            Ok(Response::client_message("Wrapping is now off."))
+        } else {
+            Ok(Response::client_message(format!(
+                "Wrapping at {width} characters."
+            )))
+        }
+    }
+}
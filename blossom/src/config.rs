@@ -2,14 +2,22 @@ use std::net::SocketAddr;
 
 use serde::{Deserialize, Serialize};
 
+use crate::{channel::ChannelDefinition, peer_queue::OverflowPolicy};
+
 #[derive(Default, Deserialize, Serialize)]
 pub struct Config {
     pub game: GameSettings,
     pub web: WebSettings,
     pub database: DatabaseSettings,
+    // Optional, so existing config files without a [tls] section still load.
+    #[serde(default)]
+    pub tls: TlsSettings,
 }
 
+// Missing fields fall back to their defaults, so settings can be added without
+// breaking existing config files.
 #[derive(Deserialize, Serialize)]
+#[serde(default)]
 pub struct GameSettings {
     pub telnet_host: String,
     pub telnet_port: u16,
@@ -17,8 +25,39 @@ pub struct GameSettings {
     pub websocket_port: u16,
     pub name: String,
     pub tick_rate: u64,
+    // A system which takes more than this percentage of a tick is logged as
+    // slow. 0 turns the warning off.
+    pub slow_system_percent: u32,
     pub save_interval: u64,
     pub default_commands: bool,
+    // Whether connections on each listener start with a PROXY protocol (v1 or
+    // v2) header. Only enable these behind a proxy which always sends one, or
+    // clients will be able to spoof their address.
+    pub telnet_proxy_protocol: bool,
+    pub websocket_proxy_protocol: bool,
+    // Input rate limiting: players can send `input_burst` lines at once, and
+    // then `input_rate` lines per second. Players who keep flooding after
+    // `flood_warnings` warnings are disconnected. Warnings wear off after ten
+    // minutes without a new one.
+    pub input_burst: u32,
+    pub input_rate: f64,
+    pub flood_warnings: u32,
+    // The most connections a single IP can have open at once; 0 is unlimited.
+    pub max_connections_per_ip: usize,
+    // How many seconds a player whose connection dropped stays in the world,
+    // waiting to reconnect.
+    pub linkdead_timeout: u64,
+    // Shown to banned connections before they are closed, followed by the
+    // reason and expiry of the ban.
+    pub ban_message: String,
+    // How many events can wait to be written to a single client. Clients which
+    // fall this far behind are handled according to `peer_queue_policy`, which
+    // is either "drop_oldest" or "disconnect".
+    pub peer_queue_size: usize,
+    pub peer_queue_policy: OverflowPolicy,
+    // The chat channels players can join. Scripts in a `channels` directory can
+    // add more, or replace these by using the same name.
+    pub channels: Vec<ChannelDefinition>,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -28,6 +67,25 @@ pub struct WebSettings {
     pub port: u16,
 }
 
+/// Settings for the secure listeners. When enabled, TLS Telnet and WebSocket
+/// (WSS) listeners are bound on their own ports, on the same hosts as their
+/// plain counterparts, which keep running as usual.
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct TlsSettings {
+    pub enabled: bool,
+    // Path to the PEM encoded certificate chain.
+    pub cert_path: String,
+    // Path to the PEM encoded private key.
+    pub key_path: String,
+    pub telnet_port: u16,
+    pub websocket_port: u16,
+    // Same as the PROXY protocol flags in `GameSettings`, for the secure
+    // listeners.
+    pub telnet_proxy_protocol: bool,
+    pub websocket_proxy_protocol: bool,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct DatabaseSettings {
     pub db_name: String,
@@ -95,6 +153,14 @@ impl Config {
         )
     }
 
+    pub fn telnet_tls_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.telnet_addr().ip(), self.tls.telnet_port)
+    }
+
+    pub fn websocket_tls_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.websocket_addr().ip(), self.tls.websocket_port)
+    }
+
     pub fn web_addr(&self) -> SocketAddr {
         SocketAddr::new(
             self.web.host.parse().expect("Failed to parse web hostname"),
@@ -123,8 +189,20 @@ impl Default for GameSettings {
             websocket_port: 5001,
             name: "Blossom".to_string(),
             tick_rate: 20,
+            slow_system_percent: 25,
             save_interval: 300,
             default_commands: true,
+            telnet_proxy_protocol: false,
+            websocket_proxy_protocol: false,
+            input_burst: 10,
+            input_rate: 4.0,
+            flood_warnings: 2,
+            max_connections_per_ip: 5,
+            linkdead_timeout: 300,
+            ban_message: "You have been banned from this server.".to_string(),
+            peer_queue_size: 256,
+            peer_queue_policy: OverflowPolicy::DropOldest,
+            channels: ChannelDefinition::defaults(),
         }
     }
 }
@@ -139,6 +217,20 @@ impl Default for WebSettings {
     }
 }
 
+impl Default for TlsSettings {
+    fn default() -> Self {
+        TlsSettings {
+            enabled: false,
+            cert_path: "game/cert.pem".to_string(),
+            key_path: "game/key.pem".to_string(),
+            telnet_port: 5443,
+            websocket_port: 5444,
+            telnet_proxy_protocol: false,
+            websocket_proxy_protocol: false,
+        }
+    }
+}
+
 impl Default for DatabaseSettings {
     fn default() -> Self {
         DatabaseSettings {
@@ -1,20 +1,76 @@
-use std::net::{IpAddr, SocketAddr};
+use std::{
+    future::Future,
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
 
+use bytes::Bytes;
 use flume::Sender;
-use futures::{SinkExt, StreamExt};
-use nectar::{event::TelnetEvent, TelnetCodec};
+use futures::{Sink, SinkExt, StreamExt};
+use nectar::{
+    error::TelnetError, event::TelnetEvent, option::TelnetOption,
+    subnegotiation::SubnegotiationType,
+};
 use tokio::net::TcpStream;
+use tokio_rustls::server::TlsStream;
 use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
 use tokio_util::codec::Framed;
 
 use crate::{
+    ansi,
+    charset::{
+        Charset, CharsetCodec, PromptMark, CHARSET_ACCEPTED, CHARSET_REJECTED, CHARSET_REQUEST,
+    },
+    constants::{CHARSET, EOR, GMCP, MCCP2, MSSP, NAWS, SGA, TTYPE},
+    envelope::{Envelope, MessageFormat},
     error::{Error, ErrorType, Result},
+    gmcp::Package,
+    line_input::LineInput,
     logging::{Action, Loggable},
+    mccp::MccpStream,
+    prompt::Prompt,
+    status::StatusHandle,
+    terminal::{Capabilities, TerminalTypeCycle},
 };
 
+// TTYPE subnegotiation commands.
+const TTYPE_IS: u8 = 0;
+const TTYPE_SEND: u8 = 1;
+
+/// How long a single write to the client may take. A client which stops
+/// reading fills up its socket buffers, after which a write would otherwise
+/// wait for it forever.
+pub const WRITE_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub type TelnetFrame<S> = Framed<MccpStream<S>, CharsetCodec>;
+
 pub enum RawStream {
-    Telnet(Framed<TcpStream, TelnetCodec>),
+    Telnet(TelnetFrame<TcpStream>),
     WebSocket(WebSocketStream<TcpStream>),
+    // TLS streams are boxed, as the TLS session state is much larger than the
+    // plain variants.
+    SecureTelnet(Box<TelnetFrame<TlsStream<TcpStream>>>),
+    SecureWebSocket(Box<WebSocketStream<TlsStream<TcpStream>>>),
+}
+
+/// Tracks which Telnet options the client has agreed to during negotiation.
+/// Everything is off until the client responds to our offer.
+#[derive(Debug, Default)]
+pub struct TelnetOptions {
+    pub gmcp: bool,
+    pub mccp: bool,
+    // The (width, height) last reported by the client via NAWS.
+    pub window_size: Option<(u16, u16)>,
+    // The charset agreed on through CHARSET, which wins over any guess made
+    // from the terminal type.
+    pub charset: Option<Charset>,
+    // Whether prompts are marked with EOR rather than GA.
+    pub eor: bool,
+    // Whether our offer of EOR is still waiting on the client's answer, so
+    // its DO isn't answered with a second WILL.
+    pub eor_offered: bool,
+    // Whether the client asked us not to send GA.
+    pub suppress_go_ahead: bool,
 }
 
 /// Represents a players connection stream, as well as their write channel half.
@@ -27,15 +83,47 @@ pub struct Connection {
     stream: RawStream,
     // Should only be None before the player has authenticated.
     pub account_id: Option<i32>,
+    pub options: TelnetOptions,
+    // The players own wrap width, used when the client doesn't report its
+    // window size. A width of 0 disables wrapping.
+    pub wrap_width: usize,
+    // What the client terminal can display, as reported through TTYPE.
+    pub capabilities: Capabilities,
+    // The message format chosen by WebSocket clients during the handshake.
+    pub format: MessageFormat,
+    ttype: TerminalTypeCycle,
+    // Whether output is being buffered rather than written out straight away.
+    held: bool,
+    // Text being written in line-input mode, if the player is writing any.
+    pub line_input: Option<LineInput>,
+    status: StatusHandle,
     tx_logger: Sender<Action>,
 }
 
 impl Connection {
-    pub fn new(addr: SocketAddr, stream: RawStream, tx_logger: Sender<Action>) -> Self {
+    pub fn new(
+        addr: SocketAddr,
+        stream: RawStream,
+        status: StatusHandle,
+        tx_logger: Sender<Action>,
+    ) -> Self {
+        let capabilities = match stream {
+            RawStream::WebSocket(_) | RawStream::SecureWebSocket(_) => Capabilities::web(),
+            RawStream::Telnet(_) | RawStream::SecureTelnet(_) => Capabilities::new(),
+        };
+
         Self {
             addr,
             stream,
             account_id: None,
+            options: TelnetOptions::default(),
+            wrap_width: 0,
+            capabilities,
+            format: MessageFormat::Text,
+            ttype: TerminalTypeCycle::default(),
+            held: false,
+            line_input: None,
+            status,
             tx_logger,
         }
     }
@@ -44,77 +132,512 @@ impl Connection {
         self.addr.ip()
     }
 
+    /// Returns the next message sent by the client. Telnet negotiation events
+    /// received in the meantime are handled internally and never returned.
     pub async fn try_next(&mut self) -> Option<String> {
+        loop {
+            let event = match &mut self.stream {
+                RawStream::Telnet(frame) => frame.next().await?,
+                RawStream::SecureTelnet(frame) => frame.next().await?,
+                RawStream::WebSocket(ws) => {
+                    return match ws.next().await {
+                        Some(Ok(Message::Text(msg))) => Some(msg),
+                        _ => None,
+                    };
+                }
+                RawStream::SecureWebSocket(ws) => {
+                    return match ws.next().await {
+                        Some(Ok(Message::Text(msg))) => Some(msg),
+                        _ => None,
+                    };
+                }
+            };
+
+            match event {
+                Ok(TelnetEvent::Message(msg)) => return Some(msg),
+                Ok(event) => {
+                    if let Err(e) = self.handle_telnet_event(event).await {
+                        tracing::error!(%e, "Error handling Telnet negotiation");
+                    }
+                }
+                Err(_) => return None,
+            }
+        }
+    }
+
+    /// Offers the Telnet options the server supports. Responses are handled
+    /// as they arrive via `try_next`, so this does not wait for the client.
+    pub async fn negotiate_options(&mut self) -> Result<()> {
+        self.send_event(TelnetEvent::Will(TelnetOption::from(GMCP)))
+            .await?;
+        self.send_event(TelnetEvent::Will(TelnetOption::from(MCCP2)))
+            .await?;
+        self.send_event(TelnetEvent::Do(TelnetOption::from(NAWS)))
+            .await?;
+        self.send_event(TelnetEvent::Do(TelnetOption::from(TTYPE)))
+            .await?;
+        self.send_event(TelnetEvent::Will(TelnetOption::from(MSSP)))
+            .await?;
+        self.send_event(TelnetEvent::Will(TelnetOption::from(CHARSET)))
+            .await?;
+        self.send_event(TelnetEvent::Will(TelnetOption::from(EOR)))
+            .await?;
+        self.options.eor_offered = true;
+
+        Ok(())
+    }
+
+    /// Sends a raw Telnet event to the client. This is a no-op for WebSocket
+    /// connections.
+    async fn send_event(&mut self, event: TelnetEvent) -> Result<()> {
+        let held = self.held;
+
         match &mut self.stream {
-            RawStream::Telnet(frame) => {
-                let msg = frame.next().await?;
+            RawStream::Telnet(frame) => write(frame, event, held).await?,
+            RawStream::SecureTelnet(frame) => write(frame, event, held).await?,
+            RawStream::WebSocket(_) | RawStream::SecureWebSocket(_) => {}
+        }
 
-                match msg {
-                    Ok(TelnetEvent::Message(msg)) => Some(msg),
-                    _ => None,
+        Ok(())
+    }
+
+    /// Buffers everything sent from now on, until `flush_output` is called.
+    /// This lets a batch of messages go out in a single write.
+    pub fn hold_output(&mut self) {
+        self.held = true;
+    }
+
+    /// Writes out everything which was buffered since `hold_output`, and goes
+    /// back to writing messages as they are sent.
+    pub async fn flush_output(&mut self) -> Result<()> {
+        self.held = false;
+
+        match &mut self.stream {
+            RawStream::Telnet(frame) => deadline(SinkExt::<TelnetEvent>::flush(frame)).await?,
+            RawStream::SecureTelnet(frame) => {
+                deadline(SinkExt::<TelnetEvent>::flush(frame)).await?
+            }
+            RawStream::WebSocket(ws) => deadline(ws.flush()).await?,
+            RawStream::SecureWebSocket(ws) => deadline(ws.flush()).await?,
+        }
+
+        Ok(())
+    }
+
+    /// Returns the next raw Telnet event from the client, or `None` if the
+    /// stream has closed or is not a Telnet stream.
+    async fn next_event(&mut self) -> Option<std::result::Result<TelnetEvent, TelnetError>> {
+        match &mut self.stream {
+            RawStream::Telnet(frame) => frame.next().await,
+            RawStream::SecureTelnet(frame) => frame.next().await,
+            RawStream::WebSocket(_) | RawStream::SecureWebSocket(_) => None,
+        }
+    }
+
+    /// Updates the negotiated option state from a non-message Telnet event.
+    async fn handle_telnet_event(&mut self, event: TelnetEvent) -> Result<()> {
+        match event {
+            TelnetEvent::Do(option) if u8::from(option) == GMCP => {
+                self.options.gmcp = true;
+            }
+            TelnetEvent::Dont(option) if u8::from(option) == GMCP => {
+                self.options.gmcp = false;
+            }
+            TelnetEvent::Do(option) if u8::from(option) == MCCP2 => {
+                self.start_compression().await?;
+            }
+            TelnetEvent::Do(option) if u8::from(option) == MSSP => {
+                let payload = self.status.read().to_subnegotiation();
+
+                self.send_event(TelnetEvent::Subnegotiate(SubnegotiationType::Unknown(
+                    TelnetOption::from(MSSP),
+                    payload,
+                )))
+                .await?;
+            }
+            TelnetEvent::Do(option) if u8::from(option) == CHARSET => {
+                self.request_charset().await?;
+            }
+            TelnetEvent::Subnegotiate(SubnegotiationType::Unknown(option, data))
+                if u8::from(option) == CHARSET =>
+            {
+                self.handle_charset(&data).await?;
+            }
+            // Options are only acknowledged when their state changes, so that
+            // the client's answer to an acknowledgement doesn't start a loop.
+            TelnetEvent::Do(option) if u8::from(option) == EOR => {
+                let offered = std::mem::take(&mut self.options.eor_offered);
+                if !self.options.eor {
+                    self.options.eor = true;
+                    if !offered {
+                        self.send_event(TelnetEvent::Will(option)).await?;
+                    }
+                }
+            }
+            TelnetEvent::Dont(option) if u8::from(option) == EOR => {
+                // A refused offer needs no reply.
+                self.options.eor_offered = false;
+                if self.options.eor {
+                    self.options.eor = false;
+                    self.send_event(TelnetEvent::Wont(option)).await?;
+                }
+            }
+            TelnetEvent::Do(option) if u8::from(option) == SGA => {
+                if !self.options.suppress_go_ahead {
+                    self.options.suppress_go_ahead = true;
+                    self.send_event(TelnetEvent::Will(option)).await?;
+                }
+            }
+            TelnetEvent::Dont(option) if u8::from(option) == SGA => {
+                if self.options.suppress_go_ahead {
+                    self.options.suppress_go_ahead = false;
+                    self.send_event(TelnetEvent::Wont(option)).await?;
+                }
+            }
+            TelnetEvent::Wont(option) if u8::from(option) == NAWS => {
+                self.options.window_size = None;
+            }
+            TelnetEvent::Will(option) if u8::from(option) == TTYPE => {
+                self.request_terminal_type().await?;
+            }
+            TelnetEvent::Wont(option) if u8::from(option) == TTYPE => {
+                self.capabilities = Capabilities::basic();
+            }
+            TelnetEvent::Subnegotiate(SubnegotiationType::Unknown(option, data))
+                if u8::from(option) == TTYPE =>
+            {
+                if data.first() == Some(&TTYPE_IS) {
+                    let name = String::from_utf8_lossy(data.get(1..).unwrap_or_default());
+                    self.update_terminal_type(&name).await?;
+                }
+            }
+            TelnetEvent::Subnegotiate(SubnegotiationType::WindowSize(width, height)) => {
+                tracing::trace!("Client window size is {}x{}", width, height);
+                self.options.window_size = Some((width, height));
+            }
+            TelnetEvent::Subnegotiate(SubnegotiationType::Unknown(option, data))
+                if u8::from(option) == GMCP =>
+            {
+                // We don't act on any client-sent packages (such as
+                // Core.Hello) yet, but they are useful when debugging.
+                tracing::trace!("Received GMCP: {}", String::from_utf8_lossy(&data));
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Offers the client every charset we support, in order of preference.
+    async fn request_charset(&mut self) -> Result<()> {
+        let mut payload = vec![CHARSET_REQUEST];
+        for charset in Charset::SUPPORTED {
+            payload.push(b';');
+            payload.extend_from_slice(charset.name().as_bytes());
+        }
+
+        self.send_event(TelnetEvent::Subnegotiate(SubnegotiationType::Unknown(
+            TelnetOption::from(CHARSET),
+            Bytes::from(payload),
+        )))
+        .await
+    }
+
+    /// Handles a CHARSET subnegotiation. This is either the clients answer to
+    /// our request, or a request of its own, which we answer the same way.
+    async fn handle_charset(&mut self, data: &[u8]) -> Result<()> {
+        let Some((&command, rest)) = data.split_first() else {
+            return Ok(());
+        };
+
+        match command {
+            CHARSET_ACCEPTED => {
+                let name = String::from_utf8_lossy(rest);
+                match Charset::from_name(&name) {
+                    Some(charset) => self.set_charset(charset, true),
+                    None => tracing::warn!("Client accepted unknown charset {}", name),
                 }
             }
-            RawStream::WebSocket(ws) => {
-                let msg = ws.next().await;
+            // Clients which can't do UTF-8 are assumed to be legacy clients,
+            // which mostly speak Latin-1.
+            CHARSET_REJECTED => self.set_charset(Charset::Latin1, true),
+            CHARSET_REQUEST => {
+                let chosen = Charset::choose(rest);
+                let reply = match chosen {
+                    Some(charset) => [&[CHARSET_ACCEPTED][..], charset.name().as_bytes()].concat(),
+                    None => vec![CHARSET_REJECTED],
+                };
 
-                match msg {
-                    Some(Ok(Message::Text(msg))) => Some(msg),
-                    _ => None,
+                // The reply still goes out in the old charset, so we only
+                // switch once it has been sent.
+                self.send_event(TelnetEvent::Subnegotiate(SubnegotiationType::Unknown(
+                    TelnetOption::from(CHARSET),
+                    Bytes::from(reply),
+                )))
+                .await?;
+
+                if let Some(charset) = chosen {
+                    self.set_charset(charset, true);
                 }
             }
+            _ => {}
         }
+
+        Ok(())
     }
 
-    /// Sends a Telnet or WebSocket message to the client.
-    pub async fn send_message(&mut self, string: &str) -> Result<()> {
+    /// Switches the charset text is sent and received in. A charset guessed
+    /// from the terminal type never overrides one which was negotiated.
+    fn set_charset(&mut self, charset: Charset, negotiated: bool) {
+        if !negotiated && self.options.charset.is_some() {
+            return;
+        }
+
+        tracing::trace!("Client charset is {}", charset);
+
         match &mut self.stream {
-            RawStream::Telnet(frame) => {
-                let event = TelnetEvent::Message(string.to_string());
+            RawStream::Telnet(frame) => frame.codec_mut().set_charset(charset),
+            RawStream::SecureTelnet(frame) => frame.codec_mut().set_charset(charset),
+            RawStream::WebSocket(_) | RawStream::SecureWebSocket(_) => return,
+        }
 
-                frame.send(event).await.map_err(|e| Error {
-                    kind: ErrorType::Internal,
-                    message: e.to_string(),
-                })
-            }
-            RawStream::WebSocket(ws) => {
-                ws.send(Message::Text(string.to_string()))
-                    .await
-                    .map_err(|e| Error {
-                        kind: ErrorType::Internal,
-                        message: e.to_string(),
-                    })
+        if negotiated {
+            self.options.charset = Some(charset);
+        }
+        self.capabilities.utf8 = charset == Charset::Utf8;
+    }
+
+    /// Asks the client for the next name in its terminal type list.
+    async fn request_terminal_type(&mut self) -> Result<()> {
+        self.send_event(TelnetEvent::Subnegotiate(SubnegotiationType::Unknown(
+            TelnetOption::from(TTYPE),
+            Bytes::from_static(&[TTYPE_SEND]),
+        )))
+        .await?;
+
+        self.ttype.requests += 1;
+
+        Ok(())
+    }
+
+    /// Updates the client capabilities from a TTYPE response. Clients which
+    /// support MTTS send their flags as the last response, which overrides
+    /// any guess made from the terminal name.
+    async fn update_terminal_type(&mut self, name: &str) -> Result<()> {
+        tracing::trace!("Client terminal type is {}", name);
+
+        if let Some(bits) = name.strip_prefix("MTTS ") {
+            if let Ok(bits) = bits.trim().parse::<u32>() {
+                self.capabilities = Capabilities::from_mtts(bits);
+
+                let charset = if self.capabilities.utf8 {
+                    Charset::Utf8
+                } else {
+                    Charset::Latin1
+                };
+                self.set_charset(charset, false);
             }
+        } else if let Some(color) = Capabilities::color_from_terminal_type(name) {
+            self.capabilities.color = color;
+        }
+
+        if self.ttype.record(name) {
+            self.request_terminal_type().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Starts MCCP2 compression. The start sequence is the last thing the client
+    /// receives uncompressed; `send` flushes it, along with anything else still
+    /// buffered by the codec, before the stream is switched over.
+    async fn start_compression(&mut self) -> Result<()> {
+        if self.options.mccp {
+            return Ok(());
         }
+
+        self.send_event(TelnetEvent::Subnegotiate(SubnegotiationType::Unknown(
+            TelnetOption::from(MCCP2),
+            Bytes::new(),
+        )))
+        .await?;
+
+        match &mut self.stream {
+            RawStream::Telnet(frame) => frame.get_mut().start_compression(),
+            RawStream::SecureTelnet(frame) => frame.get_mut().start_compression(),
+            RawStream::WebSocket(_) | RawStream::SecureWebSocket(_) => return Ok(()),
+        }
+
+        self.options.mccp = true;
+
+        Ok(())
+    }
+
+    fn is_websocket(&self) -> bool {
+        matches!(
+            self.stream,
+            RawStream::WebSocket(_) | RawStream::SecureWebSocket(_)
+        )
+    }
+
+    /// Returns the width Telnet output should be wrapped at, if any. A width
+    /// reported through NAWS always wins over the players own setting.
+    /// WebSocket clients wrap text themselves.
+    fn line_width(&self) -> Option<usize> {
+        if self.is_websocket() {
+            return None;
+        }
+
+        match self.options.window_size {
+            Some((width, _)) if width > 0 => Some(usize::from(width)),
+            _ if self.wrap_width > 0 => Some(self.wrap_width),
+            _ => None,
+        }
+    }
+
+    /// Sends a Telnet or WebSocket message to the client. This is sent as a
+    /// system message to clients which use the JSON format.
+    pub async fn send_message(&mut self, string: &str) -> Result<()> {
+        self.send(&Envelope::system(string)).await
+    }
+
+    /// Sends a message to the client in the format it asked for. Colors are
+    /// downgraded to what the client can display, and Telnet messages are
+    /// word wrapped to the clients line width.
+    pub async fn send(&mut self, envelope: &Envelope) -> Result<()> {
+        if self.format == MessageFormat::Json {
+            let json = envelope.to_json()?;
+            return self.send_websocket(json).await;
+        }
+
+        let string = ansi::render(&envelope.text, self.capabilities.color);
+
+        match self.line_width() {
+            Some(width) => self.send_text(ansi::wrap(&string, width)).await,
+            None => self.send_text(string).await,
+        }
+    }
+
+    /// Sends the prompt. Telnet clients get an EOR or GA after it, depending on
+    /// what they negotiated, so they can detect the prompt line; clients which
+    /// use the JSON format see it tagged as a prompt.
+    pub async fn send_prompt(&mut self, prompt: &Prompt) -> Result<()> {
+        self.send(&Envelope::prompt(prompt)).await?;
+
+        let mark = if self.options.eor {
+            PromptMark::EndOfRecord
+        } else if !self.options.suppress_go_ahead {
+            PromptMark::GoAhead
+        } else {
+            return Ok(());
+        };
+
+        let held = self.held;
+
+        match &mut self.stream {
+            RawStream::Telnet(frame) => write(frame, mark, held).await?,
+            RawStream::SecureTelnet(frame) => write(frame, mark, held).await?,
+            RawStream::WebSocket(_) | RawStream::SecureWebSocket(_) => {}
+        }
+
+        Ok(())
+    }
+
+    /// Sends text as-is over either kind of stream.
+    async fn send_text(&mut self, text: String) -> Result<()> {
+        if self.is_websocket() {
+            self.send_websocket(text).await
+        } else {
+            self.send_event(TelnetEvent::Message(text)).await
+        }
+    }
+
+    /// Sends a WebSocket text frame. This is a no-op for Telnet connections.
+    async fn send_websocket(&mut self, text: String) -> Result<()> {
+        let held = self.held;
+
+        match &mut self.stream {
+            RawStream::WebSocket(ws) => write(ws, Message::Text(text), held).await,
+            RawStream::SecureWebSocket(ws) => write(ws, Message::Text(text), held).await,
+            RawStream::Telnet(_) | RawStream::SecureTelnet(_) => Ok(()),
+        }
+    }
+
+    /// Sends the current server stats as a plain-text MSSP reply. This is
+    /// written as-is, so crawlers get it without any wrapping or colors.
+    pub async fn send_mssp_reply(&mut self) -> Result<()> {
+        let reply = self.status.read().to_plain_text();
+        self.send_text(reply).await
     }
 
     /// Sends a Telnet IAC (Interpret As Command) message to the client.
     pub async fn send_iac(&mut self, command: TelnetEvent) -> Result<()> {
-        match &mut self.stream {
-            RawStream::Telnet(frame) => {
-                frame.send(command).await?;
-
-                match frame.next().await {
-                    Some(Ok(response)) => response,
-                    Some(Err(e)) => {
-                        tracing::error!(%e, "Error sending IAC");
-                        return Err(Error {
-                            kind: ErrorType::Internal,
-                            message: e.to_string(),
-                        });
-                    }
-                    None => {
-                        tracing::error!("No response from IAC");
-                        return Err(Error {
-                            kind: ErrorType::Internal,
-                            message: "No response from IAC".to_string(),
-                        });
-                    }
-                };
+        if self.is_websocket() {
+            return Ok(());
+        }
+
+        self.send_event(command).await?;
 
-                Ok(())
+        let response = match self.next_event().await {
+            Some(Ok(response)) => response,
+            Some(Err(e)) => {
+                tracing::error!(%e, "Error sending IAC");
+                return Err(Error {
+                    kind: ErrorType::Internal,
+                    message: e.to_string(),
+                });
             }
-            RawStream::WebSocket(_ws) => Ok(()),
+            None => {
+                tracing::error!("No response from IAC");
+                return Err(Error {
+                    kind: ErrorType::Internal,
+                    message: "No response from IAC".to_string(),
+                });
+            }
+        };
+
+        self.handle_telnet_event(response).await
+    }
+
+    /// Sends a GMCP package to the client. This is a no-op unless the client
+    /// has negotiated GMCP, so it is always safe to call.
+    pub async fn send_gmcp(&mut self, package: &Package) -> Result<()> {
+        if !self.options.gmcp {
+            return Ok(());
         }
+
+        let payload = Bytes::from(package.encode()?.into_bytes());
+        let event = TelnetEvent::Subnegotiate(SubnegotiationType::Unknown(
+            TelnetOption::from(GMCP),
+            payload,
+        ));
+
+        self.send_event(event).await
+    }
+}
+
+/// Writes an item to a sink. While output is held, the item is only buffered,
+/// and is written out on the next flush.
+async fn write<S, I>(sink: &mut S, item: I, held: bool) -> Result<()>
+where
+    S: Sink<I> + Unpin,
+    Error: From<S::Error>,
+{
+    if held {
+        deadline(sink.feed(item)).await
+    } else {
+        deadline(sink.send(item)).await
+    }
+}
+
+/// Gives up on a write which the client hasn't taken within `WRITE_TIMEOUT`.
+async fn deadline<E>(write: impl Future<Output = std::result::Result<(), E>>) -> Result<()>
+where
+    Error: From<E>,
+{
+    match tokio::time::timeout(WRITE_TIMEOUT, write).await {
+        Ok(result) => Ok(result?),
+        Err(_) => Err(Error::new(ErrorType::Timeout, "Write timed out")),
     }
 }
 
@@ -130,6 +653,60 @@ impl Loggable for Connection {
 
 impl std::fmt::Display for Connection {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} (Telnet Protocol)", self.addr)
+        let protocol = match self.stream {
+            RawStream::Telnet(_) => "Telnet",
+            RawStream::SecureTelnet(_) => "Telnet over TLS",
+            RawStream::WebSocket(_) => "WebSocket",
+            RawStream::SecureWebSocket(_) => "WebSocket over TLS",
+        };
+
+        write!(f, "{} ({} Protocol)", self.addr, protocol)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use parking_lot::RwLock;
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::{config::Config, status::ServerStatus};
+
+    #[tokio::test(start_paused = true)]
+    async fn stalled_clients_time_out() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind listener");
+        let addr = listener.local_addr().expect("Listener has no address");
+
+        // The client connects, but never reads anything we send it.
+        let _client = TcpStream::connect(addr).await.expect("Failed to connect");
+        let (stream, peer) = listener.accept().await.expect("Failed to accept");
+
+        let frame = Framed::new(MccpStream::new(stream), CharsetCodec::new(1024));
+        let status = Arc::new(RwLock::new(ServerStatus::new(&Config::default())));
+        let (tx_logger, _rx_logger) = flume::unbounded();
+        let mut conn = Connection::new(peer, RawStream::Telnet(frame), status, tx_logger);
+
+        // Writes succeed until the socket buffers are full, and then the next
+        // one gives up rather than waiting for the client.
+        let message = "x".repeat(1000);
+        let mut result = Ok(());
+        for _ in 0..100_000 {
+            result = conn.send_message(&message).await;
+            if result.is_err() {
+                break;
+            }
+        }
+
+        assert!(matches!(
+            result,
+            Err(Error {
+                kind: ErrorType::Timeout,
+                ..
+            })
+        ));
     }
 }
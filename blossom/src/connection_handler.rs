@@ -1,52 +1,170 @@
-use std::net::SocketAddr;
+use std::{
+    future::Future,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
 
-use flume::{unbounded, Sender};
-use nectar::TelnetCodec;
+use flume::Sender;
 use sqlx::PgPool;
-use tokio::net::TcpStream;
-use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+};
+use tokio_tungstenite::{
+    tungstenite::{
+        handshake::server::{ErrorResponse, Request, Response as HandshakeResponse},
+        http::{header::SEC_WEBSOCKET_PROTOCOL, HeaderValue},
+        protocol::WebSocketConfig,
+    },
+    WebSocketStream,
+};
 use tokio_util::codec::Framed;
 
 use crate::{
     auth::authenticate,
-    blossom_log,
+    bans, blossom_log,
+    charset::CharsetCodec,
+    config::Config,
     connection::{Connection, RawStream},
-    error::Result,
+    envelope::{Envelope, MessageFormat, JSON_SUBPROTOCOL},
+    error::{Error, ErrorType, Result},
     event::{ClientEvent, Event, GameEvent},
     input::Input,
+    line_input::{LineInput, Step},
     logging::{Action, Kind, Loggable},
+    mccp::MccpStream,
+    peer_queue::{self, PeerReceiver},
+    player::PlayerId,
+    rate_limit::{ConnectionCounter, RateLimiter, Verdict, FLOOD_DISCONNECT, FLOOD_WARNING},
     response::Response,
     server::StreamType,
+    status::StatusHandle,
 };
 
+/// How long a client has to finish each step of setting up its connection, such
+/// as the PROXY header or TLS handshake, before it is dropped.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Runs a step of setting up a connection, giving up if the client doesn't
+/// finish it within `HANDSHAKE_TIMEOUT`. Otherwise a client which stalls part
+/// way through would hold its task open forever.
+pub async fn with_timeout<T, E>(
+    step: &str,
+    future: impl Future<Output = std::result::Result<T, E>>,
+) -> Result<T>
+where
+    Error: From<E>,
+{
+    match tokio::time::timeout(HANDSHAKE_TIMEOUT, future).await {
+        Ok(result) => Ok(result?),
+        Err(_) => Err(Error::new(ErrorType::Timeout, &format!("{step} timed out"))),
+    }
+}
+
+/// Handles to the rest of the server which every connection needs. A copy is
+/// handed to each new connection.
+#[derive(Clone)]
+pub struct Shared {
+    pub pg: PgPool,
+    pub config: Arc<Config>,
+    pub status: StatusHandle,
+    pub connections: ConnectionCounter,
+    pub tx_broker: Sender<Event>,
+    pub tx_logger: Sender<Action>,
+}
+
 pub async fn connection_loop(
     stream_type: StreamType,
     addr: SocketAddr,
-    stream: TcpStream,
-    pg: PgPool,
-    tx_broker: Sender<Event>,
-    tx_logger: Sender<Action>,
+    mut stream: TcpStream,
+    shared: Shared,
 ) -> Result<()> {
-    let mut conn = match stream_type {
-        StreamType::Telnet => {
-            let frame = Framed::new(stream, TelnetCodec::new(1024));
+    let Shared {
+        pg,
+        config,
+        status,
+        connections,
+        tx_broker,
+        tx_logger,
+    } = shared;
 
-            Connection::new(addr, RawStream::Telnet(frame), tx_logger.clone())
-        }
+    // Connections are counted, and checked against IP bans, before any
+    // handshake, so a single address can't tie up the server with handshakes
+    // it never means to finish. The guard is held for as long as the
+    // connection is open.
+    let incoming = Incoming {
+        ip: addr.ip(),
+        tx_logger: &tx_logger,
+    };
+    let Some(_guard) = connections.try_acquire(addr.ip(), config.game.max_connections_per_ip)
+    else {
+        blossom_log!(Kind::ConnectionLimit, &incoming);
+        refuse(
+            &stream_type,
+            &mut stream,
+            "There are too many open connections from your address.",
+        )
+        .await;
+
+        return Ok(());
+    };
+
+    if let Some(ban) = bans::find_ip_ban(addr.ip(), &pg).await? {
+        blossom_log!(Kind::Banned, format!("#{}", ban.id), &incoming);
+        refuse(
+            &stream_type,
+            &mut stream,
+            &ban.rejection(&config.game.ban_message),
+        )
+        .await;
+
+        return Ok(());
+    }
+
+    let mut format = MessageFormat::Text;
+
+    let raw_stream = match stream_type {
+        StreamType::Telnet => RawStream::Telnet(Framed::new(
+            MccpStream::new(stream),
+            CharsetCodec::new(1024),
+        )),
         StreamType::WebSocket => {
-            let config = WebSocketConfig {
-                max_message_size: Some(1400),
-                max_frame_size: Some(1400),
-                ..WebSocketConfig::default()
-            };
-            let ws = tokio_tungstenite::accept_async_with_config(stream, Some(config))
-                .await
-                .expect("Error during the websocket handshake occurred");
-
-            Connection::new(addr, RawStream::WebSocket(ws), tx_logger.clone())
+            let (ws, requested) =
+                with_timeout("WebSocket handshake", accept_websocket(stream)).await?;
+            format = requested;
+
+            RawStream::WebSocket(ws)
+        }
+        StreamType::SecureTelnet(acceptor) => {
+            let stream = with_timeout("TLS handshake", acceptor.accept(stream)).await?;
+            let frame = Framed::new(MccpStream::new(stream), CharsetCodec::new(1024));
+
+            RawStream::SecureTelnet(Box::new(frame))
+        }
+        StreamType::SecureWebSocket(acceptor) => {
+            let stream = with_timeout("TLS handshake", acceptor.accept(stream)).await?;
+            let (ws, requested) =
+                with_timeout("WebSocket handshake", accept_websocket(stream)).await?;
+            format = requested;
+
+            RawStream::SecureWebSocket(Box::new(ws))
         }
     };
 
+    let mut conn = Connection::new(addr, raw_stream, status, tx_logger.clone());
+    conn.format = format;
+
+    let mut limiter = RateLimiter::new(
+        config.game.input_burst,
+        config.game.input_rate,
+        config.game.flood_warnings,
+    );
+
+    // Offer our supported Telnet options. The client responds while we are
+    // authenticating, so everything is settled by the time they are in game.
+    conn.negotiate_options().await?;
+
     // Display the logo.
     let logo = tokio::fs::read_to_string("game/logo.txt").await;
     if let Ok(logo) = logo {
@@ -54,7 +172,13 @@ pub async fn connection_loop(
     }
 
     // Connection initialization. Log a player in (or create an account).
-    let maybe_player = authenticate(&mut conn, pg.clone()).await?;
+    let maybe_player = authenticate(
+        &mut conn,
+        &mut limiter,
+        pg.clone(),
+        &config.game.ban_message,
+    )
+    .await?;
     if maybe_player.is_none() {
         tracing::info!("Authentication failed.");
         return Ok(());
@@ -72,56 +196,109 @@ pub async fn connection_loop(
     // Store a copy of the account ID on the connection for logging.
     conn.account_id = Some(player.account.id);
 
-    // Create a channel for a connection
-    let (tx, rx) = unbounded::<Event>();
+    // Use the players wrap width until (or unless) the client reports its
+    // window size.
+    conn.wrap_width = usize::try_from(player.wrap_width).unwrap_or_default();
+
+    // Create a queue for the connection. It is bounded, so a client which stops
+    // reading can't make the server buffer its output forever.
+    let (tx, rx) = peer_queue::channel(config.game.peer_queue_size, config.game.peer_queue_policy);
 
     // Move the player off into the game thread
     tracing::trace!("{} authenticated: moving to game thread", player.name);
     tx_broker
         .send_async(Event::Client(
             player.id,
-            ClientEvent::Connect(player, Some(tx)),
+            ClientEvent::Connect(player, Some(tx.clone())),
         ))
         .await?;
 
+    let flow = match play(&mut conn, id, &rx, &tx_broker, &mut limiter).await {
+        Ok(flow) => flow,
+        // A write which fails, or which a client that stopped reading doesn't
+        // take in time, is treated like any other dropped link.
+        Err(e) => {
+            if matches!(e.kind, ErrorType::Timeout) {
+                blossom_log!(Kind::SlowConsumer, &conn);
+            }
+            tracing::info!(%e, "Lost connection to {}", addr);
+
+            Flow::Dropped
+        }
+    };
+
+    blossom_log!(Kind::Leave, &conn);
+
+    match flow {
+        Flow::Quit => {
+            tx_broker.send(Event::Client(id, ClientEvent::Disconnect(Some(tx))))?;
+            conn.send_message("\nGoodbye!\n").await?;
+        }
+        // The player now belongs to another connection, so we close without
+        // telling the game.
+        Flow::Replaced => {
+            conn.send_message("\nYou have logged in from another client.\n")
+                .await?;
+        }
+        // The connection dropped without the player quitting, so they are
+        // kept in the world for a while in case they reconnect.
+        Flow::Continue | Flow::Dropped => {
+            tx_broker.send(Event::Client(id, ClientEvent::LinkDead(Some(tx))))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs a player's session once they are in game, passing events between
+/// their connection and the game until they quit, their connection drops, or
+/// another connection takes them over.
+async fn play(
+    conn: &mut Connection,
+    id: PlayerId,
+    rx: &PeerReceiver,
+    tx_broker: &Sender<Event>,
+    limiter: &mut RateLimiter,
+) -> Result<Flow> {
     loop {
         tokio::select! {
             // Handle messages received from the broker on this peers rx channel
-            Ok(event) = rx.recv_async() => {
+            event = rx.recv() => {
+                // The queue only closes when the client fell too far behind.
+                // Their player is kept in the world, as if the link dropped.
+                let Some(event) = event else {
+                    blossom_log!(Kind::SlowConsumer, conn);
+                    return Ok(Flow::Dropped);
+                };
+
                 tracing::trace!("Received event: {:?}", event);
 
-                match event {
-                    Event::Game(id, event_type) => match event_type {
-                        GameEvent::Accepted(Response::Client(msg)) => {
-                            // Send a command response to the player
-                            conn.send_message(&msg).await?;
-                            tx_broker.send(Event::Client(id, ClientEvent::Command(Input { command: "look".to_string(), args: Vec::new() })))?;
-                        }
-                        GameEvent::Command(response)  => {
-                            match response {
-                                Response::Client(msg) => {
-                                    // Send a command response to the player
-                                    conn.send_message(&msg).await?;
+                let Event::Game(_, event) = event else {
+                    continue;
+                };
 
-                                }
-                                Response::Channel(_, msg) => {
-                                    // Send a message to a channel
-                                    conn.send_message(&msg).await?;
-                                }
-                                Response::Close => {
-                                    break;
-                                }
-                                _ => continue,
-                            }
-                        }
-                        GameEvent::Pong(response) => {
-                            if let Response::Client(msg) = response {
-                                conn.send_message(&msg).await?;
-                            }
-                        }
-                        _ => continue,
+                // The game sends all of a tick's output as one batch, which
+                // is written out to the client in a single flush.
+                let events = match event {
+                    GameEvent::Batch(events) => events,
+                    event => vec![event],
+                };
+
+                conn.hold_output();
+
+                let mut flow = Flow::Continue;
+                for event in events {
+                    flow = handle_game_event(conn, id, event, tx_broker).await?;
+
+                    if flow != Flow::Continue {
+                        break;
                     }
-                    _ => continue,
+                }
+
+                conn.flush_output().await?;
+
+                if flow != Flow::Continue {
+                    return Ok(flow);
                 }
             }
             // Handles messages received from the peer (via telnet)
@@ -129,27 +306,206 @@ pub async fn connection_loop(
                 Some(msg) => {
                     tracing::trace!("Received message: {:?}", msg);
 
+                    match limiter.check() {
+                        Verdict::Allow => {}
+                        Verdict::Warn => {
+                            blossom_log!(Kind::RateLimitWarning, msg, conn);
+                            conn.send_message(FLOOD_WARNING).await?;
+                            continue;
+                        }
+                        Verdict::Drop => {
+                            blossom_log!(Kind::RateLimitDrop, msg, conn);
+                            continue;
+                        }
+                        Verdict::Disconnect => {
+                            blossom_log!(Kind::RateLimitDisconnect, msg, conn);
+                            conn.send_message(FLOOD_DISCONNECT).await?;
+                            return Ok(Flow::Quit);
+                        }
+                    }
+
+                    // In line-input mode, lines are collected rather than
+                    // run as commands, and aren't logged.
+                    if let Some(input) = conn.line_input.as_mut() {
+                        match input.push(&msg) {
+                            Step::Continue => {}
+                            Step::Finished => {
+                                if let Some(input) = conn.line_input.take() {
+                                    let text = input.text();
+                                    tx_broker.send(Event::Client(
+                                        id,
+                                        ClientEvent::LineInput(input.purpose, text),
+                                    ))?;
+                                }
+                            }
+                            Step::Cancelled => {
+                                conn.line_input = None;
+                                conn.send_message("Cancelled.").await?;
+                                tx_broker.send(Event::Client(id, ClientEvent::Ping))?;
+                            }
+                            Step::TooLong => {
+                                conn.line_input = None;
+                                conn.send_message("That is too long, so it was thrown away.")
+                                    .await?;
+                                tx_broker.send(Event::Client(id, ClientEvent::Ping))?;
+                            }
+                        }
+                        continue;
+                    }
+
                     if msg.trim().is_empty() {
                         tx_broker.send(Event::Client(id, ClientEvent::Ping))?;
                         continue;
                     }
 
-                    blossom_log!(Kind::Message, msg.clone(), &conn);
+                    blossom_log!(Kind::Message, msg.clone(), conn);
 
                     tx_broker.send(Event::Client(id, ClientEvent::Command(Input::from(msg))))?;
                 },
                 None => {
-                    tracing::info!("Connection closed: {}", addr);
-                    break;
+                    tracing::info!("Connection closed: {}", conn.addr);
+                    return Ok(Flow::Dropped);
                 }
             }
         }
     }
+}
 
-    blossom_log!(Kind::Leave, &conn);
+/// Turns a connection away before its handshake. Only plain Telnet clients can
+/// be told why, as everyone else would need the handshake done first.
+async fn refuse(stream_type: &StreamType, stream: &mut TcpStream, message: &str) {
+    if !matches!(stream_type, StreamType::Telnet) {
+        return;
+    }
 
-    tx_broker.send(Event::Client(id, ClientEvent::Disconnect))?;
-    conn.send_message("\nGoodbye!\n").await?;
+    let message = format!("{}\r\n", message.replace('\n', "\r\n"));
+    let _ = with_timeout("Refusal", stream.write_all(message.as_bytes())).await;
+}
 
-    Ok(())
+/// Identifies a connection in the action logs before it has been set up.
+struct Incoming<'a> {
+    ip: IpAddr,
+    tx_logger: &'a Sender<Action>,
+}
+
+impl Loggable for Incoming<'_> {
+    fn identifier(&self) -> (IpAddr, Option<i32>) {
+        (self.ip, None)
+    }
+
+    fn get_logger(&self) -> Sender<Action> {
+        self.tx_logger.clone()
+    }
+}
+
+/// What the connection loop should do after handling an event from the game.
+#[derive(Debug, PartialEq, Eq)]
+enum Flow {
+    Continue,
+    // The player quit.
+    Quit,
+    // The player was taken over by another connection.
+    Replaced,
+    // The connection dropped, or the client fell too far behind.
+    Dropped,
+}
+
+/// Sends the client whatever the game sent them.
+async fn handle_game_event(
+    conn: &mut Connection,
+    id: PlayerId,
+    event: GameEvent,
+    tx_broker: &Sender<Event>,
+) -> Result<Flow> {
+    match event {
+        GameEvent::Accepted(Response::Client(msg)) => {
+            // Send a command response to the player
+            conn.send_message(&msg).await?;
+            tx_broker.send(Event::Client(
+                id,
+                ClientEvent::Command(Input {
+                    command: "look".to_string(),
+                    args: Vec::new(),
+                }),
+            ))?;
+        }
+        GameEvent::Command(response) => match response {
+            Response::Client(msg) => {
+                // Send a command response to the player
+                conn.send_message(&msg).await?;
+            }
+            Response::Channel(_, msg) => {
+                // Send a message to a channel
+                conn.send_message(&msg).await?;
+            }
+            Response::Room(view) => {
+                conn.send(&Envelope::room(view)).await?;
+            }
+            Response::Chat(_, chat) => {
+                conn.send(&Envelope::chat(&chat)).await?;
+            }
+            Response::Close => return Ok(Flow::Quit),
+            _ => {}
+        },
+        GameEvent::Pong(response) => {
+            if let Response::Client(msg) = response {
+                conn.send_message(&msg).await?;
+            }
+        }
+        GameEvent::Prompt(prompt) => {
+            conn.send_prompt(&prompt).await?;
+        }
+        GameEvent::Gmcp(package) => {
+            conn.send_gmcp(&package).await?;
+        }
+        GameEvent::WrapWidth(width) => {
+            conn.wrap_width = width;
+        }
+        GameEvent::LineInput(purpose) => {
+            conn.send_message(&LineInput::instructions()).await?;
+            conn.line_input = Some(LineInput::new(purpose));
+        }
+        GameEvent::Replaced => return Ok(Flow::Replaced),
+        _ => {}
+    }
+
+    Ok(Flow::Continue)
+}
+
+/// Performs the WebSocket handshake and returns the message format the client
+/// asked for. Clients which list the JSON subprotocol receive JSON envelopes;
+/// everyone else receives the same text as Telnet clients.
+async fn accept_websocket<S>(stream: S) -> Result<(WebSocketStream<S>, MessageFormat)>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let config = WebSocketConfig {
+        max_message_size: Some(1400),
+        max_frame_size: Some(1400),
+        ..WebSocketConfig::default()
+    };
+
+    let mut format = MessageFormat::Text;
+    let callback = |request: &Request, mut response: HandshakeResponse| {
+        let requested = request
+            .headers()
+            .get(SEC_WEBSOCKET_PROTOCOL)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.split(',').any(|p| p.trim() == JSON_SUBPROTOCOL));
+
+        if requested {
+            response.headers_mut().insert(
+                SEC_WEBSOCKET_PROTOCOL,
+                HeaderValue::from_static(JSON_SUBPROTOCOL),
+            );
+            format = MessageFormat::Json;
+        }
+
+        Ok::<_, ErrorResponse>(response)
+    };
+
+    let ws =
+        tokio_tungstenite::accept_hdr_async_with_config(stream, callback, Some(config)).await?;
+
+    Ok((ws, format))
 }
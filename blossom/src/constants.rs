@@ -6,3 +6,31 @@ pub const HTTP_METHODS: &[&str] = &[
 /// This is used for matching against HTTP traffic on the telnet stream. Telnet
 /// is only accessible with HTTP/0.9.
 pub const INVALID_HTTP_VERSIONS: &[&str] = &["HTTP/1.0", "HTTP/1.1", "HTTP/2.0"];
+
+/// Telnet option code for SGA (Suppress Go Ahead).
+pub const SGA: u8 = 3;
+
+/// Telnet option code for TTYPE (Terminal Type), which is also used to
+/// exchange MTTS (MUD Terminal Type Standard) flags.
+pub const TTYPE: u8 = 24;
+
+/// Telnet option code for EOR (End of Record), which clients use to find the
+/// end of a prompt.
+pub const EOR: u8 = 25;
+
+/// Telnet option code for NAWS (Negotiate About Window Size).
+pub const NAWS: u8 = 31;
+
+/// Telnet option code for CHARSET, which is used to agree on a character set
+/// for text. See RFC 2066.
+pub const CHARSET: u8 = 42;
+
+/// Telnet option code for MSSP (MUD Server Status Protocol).
+pub const MSSP: u8 = 70;
+
+/// Telnet option code for MCCP2 (MUD Client Compression Protocol v2).
+pub const MCCP2: u8 = 86;
+
+/// Telnet option code for GMCP (Generic MUD Communication Protocol). This is
+/// not one of the standard options, so it is referenced by its raw value.
+pub const GMCP: u8 = 201;
@@ -0,0 +1,126 @@
+use serde::Serialize;
+
+use crate::{ansi, error::Result, prompt::Prompt, response::Chat, terminal::ColorSupport};
+
+/// The JSON envelope is offered to WebSocket clients under this subprotocol.
+pub const JSON_SUBPROTOCOL: &str = "blossom.json";
+
+/// The format a WebSocket client receives messages in, as chosen during the
+/// handshake. Telnet clients always receive text.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MessageFormat {
+    // The same ANSI styled text Telnet clients receive.
+    #[default]
+    Text,
+    // Structured JSON envelopes; see `Envelope`.
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageKind {
+    System,
+    Room,
+    Chat,
+    Prompt,
+}
+
+/// A message on its way to a client, tagged with what kind of message it is.
+/// Text clients only ever see the styled `text`, while JSON clients receive
+/// the whole envelope, so they can render chat panes or HP bars without having
+/// to parse escape sequences.
+#[derive(Clone, Debug)]
+pub struct Envelope {
+    pub kind: MessageKind,
+    pub channel: Option<String>,
+    pub sender: Option<String>,
+    pub text: String,
+    // Structured data for the message, such as the values in a prompt.
+    pub data: Option<serde_json::Value>,
+}
+
+/// The JSON representation of an envelope. The styled text is sent both with
+/// all escape sequences stripped and rendered as HTML.
+#[derive(Serialize)]
+struct JsonEnvelope<'a> {
+    #[serde(rename = "type")]
+    kind: MessageKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    channel: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sender: Option<&'a str>,
+    text: String,
+    html: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<&'a serde_json::Value>,
+}
+
+impl Envelope {
+    fn new(kind: MessageKind, text: impl Into<String>) -> Self {
+        Self {
+            kind,
+            channel: None,
+            sender: None,
+            text: text.into(),
+            data: None,
+        }
+    }
+
+    pub fn system(text: impl Into<String>) -> Self {
+        Self::new(MessageKind::System, text)
+    }
+
+    pub fn room(text: impl Into<String>) -> Self {
+        Self::new(MessageKind::Room, text)
+    }
+
+    pub fn chat(chat: &Chat) -> Self {
+        Self {
+            channel: Some(chat.channel.clone()),
+            sender: Some(chat.name.clone()),
+            ..Self::new(MessageKind::Chat, chat.text.clone())
+        }
+    }
+
+    pub fn prompt(prompt: &Prompt) -> Self {
+        Self {
+            data: serde_json::to_value(prompt).ok(),
+            ..Self::new(MessageKind::Prompt, prompt.to_string())
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        let json = JsonEnvelope {
+            kind: self.kind,
+            channel: self.channel.as_deref(),
+            sender: self.sender.as_deref(),
+            text: ansi::render(&self.text, ColorSupport::None),
+            html: ansi::to_html(&self.text),
+            data: self.data.as_ref(),
+        };
+
+        Ok(serde_json::to_string(&json)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chat_to_json() {
+        let chat = Chat {
+            channel: "say".to_string(),
+            sender: 1,
+            name: "Rob".to_string(),
+            text: "Rob says, \"\x1b[1mhi\x1b[0m\"".to_string(),
+        };
+        let json = Envelope::chat(&chat).to_json();
+
+        assert!(json.is_ok());
+        assert_eq!(
+            json.unwrap_or_default(),
+            r#"{"type":"chat","channel":"say","sender":"Rob","text":"Rob says, \"hi\"","html":"Rob says, &quot;<span style=\"font-weight:bold\">hi</span>&quot;"}"#
+        );
+    }
+}
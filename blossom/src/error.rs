@@ -11,6 +11,7 @@ pub enum ErrorType {
     Script,
     Telnet,
     Io,
+    Timeout,
     Internal,
     Authentication,
     Channel,
@@ -128,6 +129,24 @@ impl From<globwalk::GlobError> for Error {
     }
 }
 
+impl From<tokio_tungstenite::tungstenite::Error> for Error {
+    fn from(err: tokio_tungstenite::tungstenite::Error) -> Self {
+        Self {
+            kind: ErrorType::Internal,
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Self {
+            kind: ErrorType::Internal,
+            message: err.to_string(),
+        }
+    }
+}
+
 impl From<argon2::password_hash::Error> for Error {
     fn from(err: argon2::password_hash::Error) -> Self {
         Self {
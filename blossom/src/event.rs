@@ -1,9 +1,15 @@
-use flume::Sender;
-
 use crate::{
+    bans::{BanRequest, Banned, Moderator},
+    gmcp::Package,
+    ignores::{IgnoreRequest, Ignored},
     input::Input,
+    line_input::Purpose,
+    mail::MailRequest,
+    peer_queue::PeerSender,
     player::{Player, PlayerId},
+    prompt::Prompt,
     response::Response,
+    tells::Tell,
 };
 
 #[derive(Debug)]
@@ -37,6 +43,35 @@ pub enum GameEvent {
     Command(Response),
     // Returns a response from a successful ClientEventType::Ping
     Pong(Response),
+    // The prompt that follows every response. This is kept apart from other
+    // output so clients can be told where the prompt ends
+    Prompt(Prompt),
+    // Out-of-band data for clients which negotiated GMCP
+    Gmcp(Package),
+    // Updates the width output is wrapped at for clients without NAWS
+    WrapWidth(usize),
+    // Tells a connection its player was taken over by a newer login
+    Replaced,
+    // Puts a connection into line-input mode, collecting lines of text until
+    // the player is done rather than treating them as commands
+    LineInput(Purpose),
+    // All of the output for a client from a single tick, which is written out
+    // to the client at once
+    Batch(Vec<GameEvent>),
+    // A moderator's ban request, which the broker carries out and answers
+    Ban(Moderator, BanRequest),
+    // A tell for a player who is offline, which the broker stores until they
+    // next log in. If nobody has that name, the broker suggests the online
+    // player which was the closest match, if any.
+    OfflineTell(String, String, Option<String>),
+    // A request about a player's mailbox, which the broker carries out and
+    // answers
+    Mail(MailRequest),
+    // A request to change who an account is ignoring, which the broker carries
+    // out and answers
+    Ignore(i32, IgnoreRequest),
+    // Asks the broker how far behind each of these players' clients are
+    PeerReport(Vec<(PlayerId, String)>),
     // A manually called event that saves a single player to the database
     Save(Player),
     // An interval-based event that saves all active players to the database
@@ -49,7 +84,12 @@ impl std::fmt::Display for ClientEvent {
             ClientEvent::Connect(p, _) => write!(f, "Connect {}", p.id),
             ClientEvent::Command(t) => write!(f, "Command {t}"),
             ClientEvent::Ping => write!(f, "Ping"),
-            ClientEvent::Disconnect => write!(f, "Disconnect"),
+            ClientEvent::Tells(tells) => write!(f, "Tells [{}]", tells.len()),
+            ClientEvent::LineInput(purpose, _) => write!(f, "LineInput {purpose}"),
+            ClientEvent::Ignores(ignores) => write!(f, "Ignores [{}]", ignores.len()),
+            ClientEvent::Banned(banned) => write!(f, "Banned {}", banned.reason),
+            ClientEvent::Disconnect(_) => write!(f, "Disconnect"),
+            ClientEvent::LinkDead(_) => write!(f, "LinkDead"),
         }
     }
 }
@@ -61,11 +101,25 @@ impl std::fmt::Display for ClientEvent {
 #[derive(Debug)]
 pub enum ClientEvent {
     // Post-authentication event that adds a player to the world
-    Connect(Player, Option<Sender<Event>>),
-    // Manually called event that removes a player from the world
-    Disconnect,
+    Connect(Player, Option<PeerSender>),
+    // Manually called event that removes a player from the world. Like
+    // `Connect`, this carries the connection's queue to the broker, so it can
+    // tell whether the connection still owns the player
+    Disconnect(Option<PeerSender>),
+    // The connection dropped without the player quitting; the player stays in
+    // the world until they reconnect or the link-dead timeout runs out
+    LinkDead(Option<PeerSender>),
     // Client-sent command
     Command(Input),
+    // Tells which were sent while the player was offline, delivered once they
+    // connect
+    Tells(Vec<Tell>),
+    // Text the player wrote in line-input mode, with what it is for
+    LineInput(Purpose, String),
+    // An account's ignore list, after it was changed
+    Ignores(Vec<Ignored>),
+    // A ban which was just stored, so anyone it covers who is online is kicked
+    Banned(Banned),
     // An event that pings the server for a response on empty input
     Ping,
 }
@@ -76,6 +130,17 @@ impl std::fmt::Display for GameEvent {
             GameEvent::Accepted(response) => write!(f, "Accepted {response}"),
             GameEvent::Command(response) => write!(f, "Command {response}"),
             GameEvent::Pong(response) => write!(f, "Pong {response}"),
+            GameEvent::Prompt(prompt) => write!(f, "Prompt {prompt}"),
+            GameEvent::Gmcp(package) => write!(f, "Gmcp {package}"),
+            GameEvent::WrapWidth(width) => write!(f, "WrapWidth {width}"),
+            GameEvent::Replaced => write!(f, "Replaced"),
+            GameEvent::LineInput(purpose) => write!(f, "LineInput {purpose}"),
+            GameEvent::Batch(events) => write!(f, "Batch [{}]", events.len()),
+            GameEvent::Ban(_, request) => write!(f, "Ban {request}"),
+            GameEvent::OfflineTell(recipient, ..) => write!(f, "OfflineTell {recipient}"),
+            GameEvent::Mail(request) => write!(f, "Mail {request}"),
+            GameEvent::Ignore(account, request) => write!(f, "Ignore {account} {request}"),
+            GameEvent::PeerReport(players) => write!(f, "PeerReport [{}]", players.len()),
             GameEvent::Save(player) => write!(f, "Save {}", player.id),
             GameEvent::GlobalSave(players) => {
                 write!(
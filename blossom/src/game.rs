@@ -1,21 +1,31 @@
 use flume::{Receiver, Sender};
 
 use crate::{
+    channel::ChannelDefinition,
     command::GameCommand,
     commands::{
         admin::{
-            help::AdminHelp, player_info::PlayerInfo, room_info::RoomInfo, shutdown::Shutdown,
-            system_control::SystemsControl, version::Version, world_info::WorldInfo,
+            ban::Ban, ban_list::BanList, help::AdminHelp, player_info::PlayerInfo, queues::Queues,
+            room_info::RoomInfo, shutdown::Shutdown, system_control::SystemsControl, unban::Unban,
+            version::Version, world_info::WorldInfo,
         },
         afk::Afk,
         brief::Brief,
+        channel::ChannelCommand,
+        emote::Emote,
         help::Help,
+        ignore::Ignore,
         look::Look,
+        mail::MailCommand,
         ooc::GlobalChat,
         quit::Quit,
+        reply::Reply,
         say::Say,
+        tell::Tell,
+        unignore::Unignore,
         walk::Walk,
         who::Who,
+        wrap::Wrap,
     },
     config::Config,
     event::Event,
@@ -23,7 +33,13 @@ use crate::{
     region::{AreaBuilder, RegionBuilder},
     room::RoomBuilder,
     scripting::{create_engine, get_game_objects},
-    systems::{global_save::GlobalSave, watcher::SystemWatcher},
+    status::StatusHandle,
+    system::{Stage, SystemSchedule},
+    systems::{
+        global_save::GlobalSave, gmcp::GmcpWatcher, linkdead::LinkDeadReaper,
+        watcher::SystemWatcher,
+    },
+    timer::Timer,
     world::World,
 };
 
@@ -33,26 +49,56 @@ use crate::{
 pub struct Game;
 
 impl Game {
-    pub fn run(mut world: World, config: &Config, rx: Receiver<Event>, tx: Sender<Event>) {
+    pub fn run(
+        mut world: World,
+        config: &Config,
+        status: StatusHandle,
+        rx: Receiver<Event>,
+        tx: Sender<Event>,
+    ) {
         world.rx = rx;
         world.broker = tx;
+        world.status = status;
+        world.systems.slow_system_percent = config.game.slow_system_percent;
 
         let engine = create_engine();
 
-        world.add_system("watcher", SystemWatcher::new());
-        world.add_system("global_save", GlobalSave::new(config.game.save_interval));
-        // world.add_system("spawner", Spawner::new(300));
+        world.add_system(
+            "watcher",
+            SystemWatcher::new(),
+            SystemSchedule::every_tick().stage(Stage::First),
+        );
+        world.add_system(
+            "global_save",
+            GlobalSave,
+            SystemSchedule::every_seconds(config.game.save_interval).stage(Stage::Last),
+        );
+        world.add_system("gmcp", GmcpWatcher::new(), SystemSchedule::every_tick());
+        world.add_system(
+            "linkdead",
+            LinkDeadReaper::new(config.game.linkdead_timeout),
+            SystemSchedule::every_seconds(1).when_players_online(),
+        );
+        // world.add_system("spawner", Spawner, SystemSchedule::every_seconds(300));
 
         if config.game.default_commands {
             world.add_command(Afk::create(), Afk::run);
             world.add_command(Brief::create(), Brief::run);
+            world.add_command(ChannelCommand::create(), ChannelCommand::run);
+            world.add_command(Emote::create(), Emote::run);
             world.add_command(GlobalChat::create(), GlobalChat::run);
             world.add_command(Help::create(), Help::run);
+            world.add_command(Ignore::create(), Ignore::run);
             world.add_command(Look::create(), Look::run);
+            world.add_command(MailCommand::create(), MailCommand::run);
             world.add_command(Quit::create(), Quit::run);
             world.add_command(Say::create(), Say::run);
+            world.add_command(Tell::create(), Tell::run);
+            world.add_command(Reply::create(), Reply::run);
+            world.add_command(Unignore::create(), Unignore::run);
             world.add_command(Walk::create(), Walk::run);
             world.add_command(Who::create(), Who::run);
+            world.add_command(Wrap::create(), Wrap::run);
             world.add_command(Shutdown::create(), Shutdown::run);
             world.add_command(WorldInfo::create(), WorldInfo::run);
             world.add_command(SystemsControl::create(), SystemsControl::run);
@@ -60,6 +106,10 @@ impl Game {
             world.add_command(AdminHelp::create(), AdminHelp::run);
             world.add_command(Version::create(), Version::run);
             world.add_command(RoomInfo::create(), RoomInfo::run);
+            world.add_command(Ban::create(), Ban::run);
+            world.add_command(Unban::create(), Unban::run);
+            world.add_command(BanList::create(), BanList::run);
+            world.add_command(Queues::create(), Queues::run);
         }
 
         // Game initialization for locations is done sequentially:
@@ -103,6 +153,18 @@ impl Game {
             }
         }
 
+        // Load all chat channels. Channels from scripts replace those of the
+        // same name from the config file.
+        for definition in &config.game.channels {
+            world.channels.add(definition.clone());
+        }
+
+        if let Ok(channels) = get_game_objects::<ChannelDefinition>(&engine, "channels") {
+            for definition in channels {
+                world.channels.add(definition);
+            }
+        }
+
         // Load all monster templates
         if let Ok(monsters) = get_game_objects::<MonsterTemplate>(&engine, "monsters") {
             for template in monsters {
@@ -112,6 +174,10 @@ impl Game {
             }
         }
 
+        // The clock starts once everything is loaded, so the time spent loading
+        // isn't counted as missed ticks.
+        world.timer = Timer::new(config.game.tick_rate);
+
         tokio::task::spawn_blocking(move || world.start_loop());
     }
 }
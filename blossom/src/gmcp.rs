@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::{player::Player, quickmap::QuickMap, room::Room, vec3::Vec3};
+
+/// Represents a GMCP (Generic MUD Communication Protocol) package. Packages are
+/// out-of-band data sent alongside the plain-text responses, which MUD clients
+/// such as Mudlet use to drive gauges, mappers, and other UI elements without
+/// having to scrape the game output.
+///
+/// Packages are only delivered to Telnet clients which have negotiated GMCP;
+/// for everyone else they are silently dropped by the connection.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Package {
+    CharVitals(Vitals),
+    CharStatus(Status),
+    RoomInfo(RoomInfo),
+    // A game-specific package, represented as a package name (eg.
+    // "Char.Skills") and an arbitrary JSON body.
+    Custom(String, serde_json::Value),
+}
+
+impl Package {
+    /// Returns the package name as it appears on the wire.
+    pub fn name(&self) -> &str {
+        match self {
+            Package::CharVitals(_) => "Char.Vitals",
+            Package::CharStatus(_) => "Char.Status",
+            Package::RoomInfo(_) => "Room.Info",
+            Package::Custom(name, _) => name,
+        }
+    }
+
+    /// Encodes the package as `<Package.Name> <JSON>`, which is the payload of
+    /// a GMCP subnegotiation.
+    pub fn encode(&self) -> Result<String, serde_json::Error> {
+        let body = match self {
+            Package::CharVitals(vitals) => serde_json::to_string(vitals)?,
+            Package::CharStatus(status) => serde_json::to_string(status)?,
+            Package::RoomInfo(info) => serde_json::to_string(info)?,
+            Package::Custom(_, value) => serde_json::to_string(value)?,
+        };
+
+        Ok(format!("{} {}", self.name(), body))
+    }
+}
+
+impl std::fmt::Display for Package {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// The `Char.Vitals` package. Mirrors the values displayed in the prompt.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Vitals {
+    pub hp: i32,
+    pub maxhp: i32,
+    pub mp: i32,
+    pub maxmp: i32,
+}
+
+impl From<&Player> for Vitals {
+    fn from(player: &Player) -> Self {
+        Self {
+            hp: player.health,
+            maxhp: player.max_health,
+            mp: player.mana,
+            maxmp: player.max_mana,
+        }
+    }
+}
+
+/// The `Char.Status` package.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Status {
+    pub name: String,
+    pub level: i32,
+    pub xp: i32,
+    pub xp_to_level: i32,
+    pub afk: bool,
+}
+
+impl From<&Player> for Status {
+    fn from(player: &Player) -> Self {
+        Self {
+            name: player.name.clone(),
+            level: player.level,
+            xp: player.xp,
+            xp_to_level: player.xp_to_level,
+            afk: player.afk,
+        }
+    }
+}
+
+/// The `Room.Info` package. Exits are represented as a map of direction to the
+/// number of the room on the other side, which is what client mappers expect.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RoomInfo {
+    pub num: u32,
+    pub name: String,
+    pub area: String,
+    pub coords: Vec3,
+    pub exits: BTreeMap<String, u32>,
+}
+
+impl RoomInfo {
+    pub fn new(room: &Room, rooms: &QuickMap<Vec3, Room>) -> Self {
+        let exits = room
+            .exits
+            .iter()
+            .filter_map(|direction| {
+                rooms
+                    .get(&(room.position + Vec3::from(*direction)))
+                    .map(|r| (direction.to_string(), r.entity_id.id))
+            })
+            .collect();
+
+        Self {
+            num: room.entity_id.id,
+            name: room.name.clone(),
+            area: room.area.clone(),
+            coords: room.position,
+            exits,
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// Runs `func`, catching it if it panics, so a bug in a single command or
+/// system can't take down the game loop. Returns the panic's message if it
+/// panicked.
+///
+/// Nothing is rolled back, so any changes `func` made to the world before it
+/// panicked are kept.
+pub fn catch_panic<T>(func: impl FnOnce() -> T) -> Result<T, String> {
+    catch_unwind(AssertUnwindSafe(func)).map_err(|payload| {
+        // Panics almost always carry a message, as either a `&str` or a
+        // `String` when it was formatted.
+        payload
+            .downcast_ref::<&str>()
+            .map(ToString::to_string)
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown cause".to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catches_panics() {
+        assert_eq!(catch_panic(|| 1 + 1), Ok(2));
+        assert_eq!(
+            catch_panic(|| panic!("oops")),
+            Err::<(), _>("oops".to_string())
+        );
+        assert_eq!(
+            catch_panic(|| panic!("oops {}", 2)),
+            Err::<(), _>("oops 2".to_string())
+        );
+    }
+}
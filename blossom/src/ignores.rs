@@ -0,0 +1,210 @@
+use sqlx::PgPool;
+
+use crate::{account::Account, error::Result, player::PlayerId, role::Role};
+
+/// A player someone is ignoring. Ignoring a player ignores every character on
+/// their account.
+#[derive(Clone, Debug)]
+pub struct Ignored {
+    pub account_id: i32,
+    pub name: String,
+}
+
+/// A request from a player to change who they are ignoring, carried out by the
+/// broker as it holds the database pool.
+#[derive(Clone, Debug)]
+pub enum IgnoreRequest {
+    Add(String),
+    Remove(String),
+}
+
+impl std::fmt::Display for IgnoreRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IgnoreRequest::Add(name) => write!(f, "Add {name}"),
+            IgnoreRequest::Remove(name) => write!(f, "Remove {name}"),
+        }
+    }
+}
+
+/// What the broker tells the game after handling an `IgnoreRequest`.
+pub struct Outcome {
+    // The message for the player who made the request.
+    pub reply: String,
+    // The account's ignore list, so the game's copy stays up to date.
+    pub ignores: Option<Vec<Ignored>>,
+}
+
+impl From<String> for Outcome {
+    fn from(reply: String) -> Self {
+        Outcome {
+            reply,
+            ignores: None,
+        }
+    }
+}
+
+/// Returns everyone an account is ignoring.
+pub async fn load(account: i32, pg: &PgPool) -> Result<Vec<Ignored>> {
+    let ignores = sqlx::query_as!(
+        Ignored,
+        "select p.account_id, p.name
+        from ignores i
+        join players p on p.id = i.player_id
+        where i.account_id = $1
+        order by p.name",
+        account,
+    )
+    .fetch_all(pg)
+    .await?;
+
+    Ok(ignores)
+}
+
+/// Whether the player named `recipient` is ignoring `sender`. This is for
+/// messages to players who are offline, whose ignore list isn't loaded.
+pub async fn is_ignoring(recipient: &str, sender: PlayerId, pg: &PgPool) -> Result<bool> {
+    let record = sqlx::query!(
+        r#"select exists (
+            select 1
+            from ignores i
+            join players r on r.account_id = i.account_id
+            join players ignored on ignored.id = i.player_id
+            join players s on s.account_id = ignored.account_id
+            join accounts a on a.id = s.account_id
+            where r.name = $1
+            and s.id = $2
+            and not a.roles && array['admin', 'moderator']::varchar[]
+        ) as "ignoring!""#,
+        recipient,
+        sender,
+    )
+    .fetch_one(pg)
+    .await?;
+
+    Ok(record.ignoring)
+}
+
+/// Starts ignoring the player named `name`.
+async fn add(account: i32, name: &str, pg: &PgPool) -> Result<String> {
+    let Some(target) = sqlx::query!(
+        "select p.id, p.account_id, p.name, a.roles
+        from players p
+        join accounts a on a.id = p.account_id
+        where p.name = $1",
+        name,
+    )
+    .fetch_optional(pg)
+    .await?
+    else {
+        return Ok(format!("There is no player named {name}."));
+    };
+
+    if target.account_id == account {
+        return Ok("You can't ignore yourself.".to_string());
+    }
+
+    // Staff are whoever the game itself treats as moderators.
+    let roles = target
+        .roles
+        .iter()
+        .filter_map(|role| role.parse::<Role>().ok())
+        .collect();
+    if Account::new(target.account_id, roles).is_moderator() {
+        return Ok(format!("{} is staff, and can't be ignored.", target.name));
+    }
+
+    let result = sqlx::query!(
+        "insert into ignores (account_id, player_id)
+        values ($1, $2)
+        on conflict do nothing",
+        account,
+        target.id,
+    )
+    .execute(pg)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Ok(format!("You are already ignoring {}.", target.name));
+    }
+
+    Ok(format!("You are now ignoring {}.", target.name))
+}
+
+/// Stops ignoring the account the player named `name` belongs to, whichever of
+/// its characters was ignored.
+async fn remove(account: i32, name: &str, pg: &PgPool) -> Result<String> {
+    let result = sqlx::query!(
+        "delete from ignores i
+        using players ignored, players p
+        where ignored.id = i.player_id
+        and ignored.account_id = p.account_id
+        and i.account_id = $1
+        and p.name = $2",
+        account,
+        name,
+    )
+    .execute(pg)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Ok(format!("You aren't ignoring {name}."));
+    }
+
+    Ok(format!("You are no longer ignoring {name}."))
+}
+
+/// Carries out a request to change who an account is ignoring, and returns the
+/// list as it now stands.
+pub async fn handle(request: IgnoreRequest, account: i32, pg: &PgPool) -> Outcome {
+    let result = match request {
+        IgnoreRequest::Add(name) => add(account, &name, pg).await,
+        IgnoreRequest::Remove(name) => remove(account, &name, pg).await,
+    };
+
+    let reply = match result {
+        Ok(reply) => reply,
+        Err(e) => {
+            tracing::error!(%e, "Failed to update ignore list");
+            return Outcome::from("Something went wrong; please try again later.".to_string());
+        }
+    };
+
+    match load(account, pg).await {
+        Ok(ignores) => Outcome {
+            reply,
+            ignores: Some(ignores),
+        },
+        Err(e) => {
+            tracing::error!(%e, "Failed to load ignore list");
+            Outcome::from(reply)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::*;
+    use crate::{account::Account, player::Player, role::Role};
+
+    #[test]
+    fn staff_cant_be_ignored() {
+        let mut player = Player::new(1, IpAddr::V4(Ipv4Addr::LOCALHOST));
+        player.ignores = vec![
+            Ignored {
+                account_id: 2,
+                name: "Bob".to_string(),
+            },
+            Ignored {
+                account_id: 3,
+                name: "Carol".to_string(),
+            },
+        ];
+
+        assert!(player.is_ignoring(&Account::new(2, vec![Role::Player])));
+        assert!(!player.is_ignoring(&Account::new(4, vec![Role::Player])));
+        assert!(!player.is_ignoring(&Account::new(3, vec![Role::Moderator])));
+    }
+}
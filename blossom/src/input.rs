@@ -26,18 +26,17 @@ impl Input {
     /// This will return the index of the most relevant match within the
     /// original collection.
     ///
-    /// This uses the `SimSearch` levenshtein implementation, which has a
-    /// limitation of ASCII-only strings. UTF-8 string compatability can be
-    /// enabled by removing this option. The benefit is that the search is
-    /// SIMD-powered and MUCH faster.
+    /// This uses the default `SimSearch` comparison rather than its SIMD
+    /// levenshtein implementation, as the latter only works on ASCII strings
+    /// and players can target things by their UTF-8 names.
     ///
-    /// @TODO: Make this configurable via the game config file. @TODO: Return
-    /// the reference to T instead of the index in the collection.
+    /// @TODO: Return the reference to T instead of the index in the
+    /// collection.
     pub fn fuzzy_match<T>(&self, values: &[&T]) -> Option<usize>
     where
         for<'a> &'a T: Searchable,
     {
-        let options = SearchOptions::new().levenshtein(true).threshold(0.5);
+        let options = SearchOptions::new().threshold(0.5);
         let mut engine = SimSearch::new_with(options);
 
         values.iter().enumerate().for_each(|(i, s)| {
@@ -3,8 +3,12 @@
 #![forbid(clippy::indexing_slicing)]
 
 pub mod account;
+pub mod ansi;
 pub mod auth;
+pub mod bans;
 pub mod broker;
+pub mod channel;
+pub mod charset;
 pub mod command;
 pub mod commands;
 pub mod config;
@@ -15,28 +19,45 @@ pub mod context;
 pub mod database;
 pub mod direction;
 pub mod entity;
+pub mod envelope;
 pub mod error;
 pub mod event;
 pub mod game;
+pub mod gmcp;
+pub mod guard;
+pub mod ignores;
 pub mod input;
+pub mod line_input;
 pub mod logging;
+pub mod mail;
+pub mod mccp;
 pub mod monster;
+pub mod outbox;
+pub mod peer_queue;
 pub mod player;
 pub mod prelude;
 pub mod prompt;
+pub mod proxy_protocol;
 pub mod quickmap;
+pub mod rate_limit;
 pub mod region;
 pub mod response;
 pub mod role;
 pub mod room;
+pub mod scheduler;
 pub mod scripting;
 pub mod searchable;
 pub mod server;
+pub mod status;
 pub mod stores;
 pub mod system;
 pub mod systems;
+pub mod tells;
+pub mod terminal;
 pub mod theme;
 pub mod timer;
+pub mod timings;
+pub mod tls;
 pub mod utils;
 pub mod vec3;
 pub mod web;
@@ -0,0 +1,138 @@
+use crate::mail::MailRecipient;
+
+// Entered on a line by itself to finish or throw away the text.
+pub const FINISH: &str = ".";
+pub const CANCEL: &str = "~q";
+
+// Limits on the size of a block of text, so a client can't make the server hold
+// onto an endless amount of it.
+const MAX_LINES: usize = 100;
+const MAX_LENGTH: usize = 8000;
+
+/// What a block of text is being written for. The game is told once the text is
+/// done, and decides what to do with it.
+#[derive(Clone, Debug)]
+pub enum Purpose {
+    Mail {
+        recipient: MailRecipient,
+        subject: String,
+    },
+}
+
+impl std::fmt::Display for Purpose {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Purpose::Mail { recipient, .. } => write!(f, "Mail {recipient}"),
+        }
+    }
+}
+
+/// The result of adding a line to a block of text.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Step {
+    Continue,
+    Finished,
+    Cancelled,
+    // The text went over the size limits, so it was thrown away.
+    TooLong,
+}
+
+/// Collects lines from a client until they finish or cancel, rather than
+/// parsing each line as a command. This is how players write multi-line text,
+/// such as the body of a mail.
+#[derive(Debug)]
+pub struct LineInput {
+    pub purpose: Purpose,
+    lines: Vec<String>,
+    length: usize,
+}
+
+impl LineInput {
+    pub fn new(purpose: Purpose) -> Self {
+        Self {
+            purpose,
+            lines: Vec::new(),
+            length: 0,
+        }
+    }
+
+    pub fn push(&mut self, line: &str) -> Step {
+        match line.trim() {
+            // There is nothing to send, so it's as if the player cancelled.
+            FINISH if self.is_blank() => return Step::Cancelled,
+            FINISH => return Step::Finished,
+            CANCEL => return Step::Cancelled,
+            _ => {}
+        }
+
+        let line = line.trim_end();
+        self.length += line.len();
+
+        if self.lines.len() >= MAX_LINES || self.length > MAX_LENGTH {
+            return Step::TooLong;
+        }
+
+        self.lines.push(line.to_string());
+
+        Step::Continue
+    }
+
+    /// The text written so far.
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    /// Whether nothing but blank lines has been written so far.
+    fn is_blank(&self) -> bool {
+        self.lines.iter().all(|line| line.trim().is_empty())
+    }
+
+    /// Explains how to finish or cancel, for whoever asked for the text.
+    pub fn instructions() -> String {
+        format!("Enter your message. Type {FINISH} on a line by itself to send it, or {CANCEL} to cancel.")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input() -> LineInput {
+        LineInput::new(Purpose::Mail {
+            recipient: MailRecipient::Player("Alice".to_string()),
+            subject: "Hello".to_string(),
+        })
+    }
+
+    #[test]
+    fn collects_until_finished() {
+        let mut input = input();
+
+        assert_eq!(input.push("Hi Alice,"), Step::Continue);
+        assert_eq!(input.push(""), Step::Continue);
+        assert_eq!(input.push("See you soon.  "), Step::Continue);
+        assert_eq!(input.push(" . "), Step::Finished);
+        assert_eq!(input.text(), "Hi Alice,\n\nSee you soon.");
+    }
+
+    #[test]
+    fn blank_text_is_cancelled() {
+        let mut empty = input();
+        assert_eq!(empty.push(FINISH), Step::Cancelled);
+
+        let mut blank = input();
+        assert_eq!(blank.push("   "), Step::Continue);
+        assert_eq!(blank.push(FINISH), Step::Cancelled);
+    }
+
+    #[test]
+    fn stops_at_limits() {
+        let mut input = input();
+
+        for _ in 0..MAX_LINES {
+            assert_eq!(input.push("line"), Step::Continue);
+        }
+        assert_eq!(input.push("one too many"), Step::TooLong);
+        assert_eq!(input.push(CANCEL), Step::Cancelled);
+    }
+}
@@ -108,6 +108,23 @@ pub enum Kind {
     FailedJoin,
     Leave,
     Message,
+    // The connection was refused because its IP has too many open connections.
+    ConnectionLimit,
+    // The player was warned for sending input too quickly.
+    RateLimitWarning,
+    // Input was dropped because the player was sending it too quickly.
+    RateLimitDrop,
+    // The player was disconnected for repeatedly flooding.
+    RateLimitDisconnect,
+    // The connection was refused because its IP or account is banned.
+    Banned,
+    // A moderator banned an IP, CIDR range or account.
+    BanAdded,
+    // A moderator lifted a ban.
+    BanLifted,
+    // The connection was closed because the client fell too far behind on its
+    // output.
+    SlowConsumer,
 }
 
 impl std::fmt::Display for Kind {
@@ -118,6 +135,14 @@ impl std::fmt::Display for Kind {
             Kind::FailedJoin => write!(f, "failed_join"),
             Kind::Leave => write!(f, "leave"),
             Kind::Message => write!(f, "message"),
+            Kind::ConnectionLimit => write!(f, "connection_limit"),
+            Kind::RateLimitWarning => write!(f, "rate_limit_warning"),
+            Kind::RateLimitDrop => write!(f, "rate_limit_drop"),
+            Kind::RateLimitDisconnect => write!(f, "rate_limit_disconnect"),
+            Kind::Banned => write!(f, "banned"),
+            Kind::BanAdded => write!(f, "ban_added"),
+            Kind::BanLifted => write!(f, "ban_lifted"),
+            Kind::SlowConsumer => write!(f, "slow_consumer"),
         }
     }
 }
@@ -0,0 +1,374 @@
+use sqlx::PgPool;
+use time::OffsetDateTime;
+
+use crate::{error::Result, ignores, line_input::Purpose, player::PlayerId};
+
+// Subjects are shown on one line in the mailbox, so they are kept short.
+pub const MAX_SUBJECT_LENGTH: usize = 60;
+
+/// Who a mail is going to.
+#[derive(Clone, Debug)]
+pub enum MailRecipient {
+    Player(String),
+    // Whoever sent the player one of their own mails, which the reply also
+    // takes its subject from.
+    ReplyTo(i32),
+}
+
+impl std::fmt::Display for MailRecipient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MailRecipient::Player(name) => write!(f, "{name}"),
+            MailRecipient::ReplyTo(id) => write!(f, "the sender of #{id}"),
+        }
+    }
+}
+
+/// A request from a player about their mailbox, carried out by the broker as it
+/// holds the database pool.
+#[derive(Clone, Debug)]
+pub enum MailRequest {
+    List,
+    Read(i32),
+    Delete(i32),
+    // Checks a mail can be sent before the player writes it, so they don't
+    // write a whole body only to find it has nowhere to go.
+    Write {
+        recipient: MailRecipient,
+        subject: String,
+    },
+    Send {
+        recipient: MailRecipient,
+        subject: String,
+        body: String,
+    },
+}
+
+impl std::fmt::Display for MailRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MailRequest::List => write!(f, "List"),
+            MailRequest::Read(id) => write!(f, "Read {id}"),
+            MailRequest::Delete(id) => write!(f, "Delete {id}"),
+            MailRequest::Write { recipient, .. } => write!(f, "Write {recipient}"),
+            MailRequest::Send { recipient, .. } => write!(f, "Send {recipient}"),
+        }
+    }
+}
+
+/// A mail in a player's mailbox.
+#[derive(Debug)]
+pub struct Mail {
+    pub id: i32,
+    pub sender: String,
+    pub subject: String,
+    pub body: String,
+    pub read: bool,
+    pub sent_on: OffsetDateTime,
+}
+
+impl Mail {
+    /// The full mail, as shown when it is read.
+    pub fn view(&self) -> String {
+        format!(
+            "Mail #{}\nFrom: {}\nDate: {}\nSubject: {}\n\n{}",
+            self.id,
+            self.sender,
+            self.sent_on.date(),
+            self.subject,
+            self.body
+        )
+    }
+}
+
+impl std::fmt::Display for Mail {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} #{:<5} {:<16} {:<12} {}",
+            if self.read { " " } else { "*" },
+            self.id,
+            self.sender,
+            self.sent_on.date(),
+            self.subject
+        )
+    }
+}
+
+/// What the broker tells players after handling a `MailRequest`.
+pub struct Outcome {
+    // The message for the player who made the request.
+    pub reply: String,
+    // A player who was sent new mail, and should be told about it.
+    pub notify: Option<PlayerId>,
+    // The mail the player can go on to write, once its recipient checks out.
+    pub write: Option<Purpose>,
+}
+
+impl From<String> for Outcome {
+    fn from(reply: String) -> Self {
+        Outcome {
+            reply,
+            notify: None,
+            write: None,
+        }
+    }
+}
+
+/// Returns every mail in a player's mailbox, newest first.
+async fn list(recipient: PlayerId, pg: &PgPool) -> Result<Vec<Mail>> {
+    let mail = sqlx::query_as!(
+        Mail,
+        r#"select m.id, p.name as "sender!", m.subject, m.body, m.read, m.created_on as sent_on
+        from mail m
+        join players p on p.id = m.sender
+        where m.recipient = $1
+        order by m.id desc"#,
+        recipient,
+    )
+    .fetch_all(pg)
+    .await?;
+
+    Ok(mail)
+}
+
+/// Returns a mail from a player's mailbox and marks it as read.
+async fn read(id: i32, recipient: PlayerId, pg: &PgPool) -> Result<Option<Mail>> {
+    let mail = sqlx::query_as!(
+        Mail,
+        r#"update mail m
+        set read = true
+        from players p
+        where p.id = m.sender
+        and m.id = $1
+        and m.recipient = $2
+        returning m.id as "id!", p.name as "sender!", m.subject as "subject!", m.body as "body!", m.read as "read!", m.created_on as "sent_on!""#,
+        id,
+        recipient,
+    )
+    .fetch_optional(pg)
+    .await?;
+
+    Ok(mail)
+}
+
+/// Deletes a mail from a player's mailbox. Returns whether there was one to
+/// delete.
+async fn delete(id: i32, recipient: PlayerId, pg: &PgPool) -> Result<bool> {
+    let record = sqlx::query!(
+        "delete from mail where id = $1 and recipient = $2 returning id",
+        id,
+        recipient
+    )
+    .fetch_optional(pg)
+    .await?;
+
+    Ok(record.is_some())
+}
+
+/// Returns the name of the player named `name`, as it is stored, or `None` if
+/// there is no such player.
+async fn find(name: &str, pg: &PgPool) -> Result<Option<String>> {
+    let record = sqlx::query!("select name from players where name = $1", name)
+        .fetch_optional(pg)
+        .await?;
+
+    Ok(record.map(|record| record.name))
+}
+
+/// Stores a new mail. Returns the recipient's ID and name, or `None` if there
+/// is no such player.
+async fn send(
+    sender: PlayerId,
+    recipient: &str,
+    subject: &str,
+    body: &str,
+    pg: &PgPool,
+) -> Result<Option<(PlayerId, String)>> {
+    let record = sqlx::query!(
+        r#"with target as (select id, name from players where name = $2),
+        inserted as (
+            insert into mail (sender, recipient, subject, body)
+            select $1, target.id, $3, $4 from target
+            returning recipient
+        )
+        select target.id as "id!", target.name as "name!" from target join inserted on inserted.recipient = target.id"#,
+        sender,
+        recipient,
+        subject,
+        body,
+    )
+    .fetch_optional(pg)
+    .await?;
+
+    Ok(record.map(|record| (record.id, record.name)))
+}
+
+/// Finds who to send a reply to, and the subject to use, from a mail in a
+/// player's mailbox.
+async fn reply_to(id: i32, recipient: PlayerId, pg: &PgPool) -> Result<Option<(String, String)>> {
+    let record = sqlx::query!(
+        "select p.name, m.subject
+        from mail m
+        join players p on p.id = m.sender
+        where m.id = $1
+        and m.recipient = $2",
+        id,
+        recipient,
+    )
+    .fetch_optional(pg)
+    .await?;
+
+    Ok(record.map(|record| (record.name, reply_subject(&record.subject))))
+}
+
+/// Prefixes a subject with "Re:", unless it already has one.
+fn reply_subject(subject: &str) -> String {
+    if subject.to_lowercase().starts_with("re:") {
+        subject.to_string()
+    } else {
+        format!("Re: {subject}")
+    }
+}
+
+/// Carries out a player's request for their mailbox.
+pub async fn handle(request: MailRequest, id: PlayerId, pg: &PgPool) -> Outcome {
+    let result = match request {
+        MailRequest::List => list(id, pg).await.map(|mail| {
+            if mail.is_empty() {
+                return "Your mailbox is empty.".to_string();
+            }
+
+            let mut lines = vec![format!(
+                "  {:<6} {:<16} {:<12} {}",
+                "ID", "From", "Date", "Subject"
+            )];
+            lines.extend(mail.iter().map(ToString::to_string));
+            lines.push("\nType `mail read <id>` to read a mail.".to_string());
+
+            lines.join("\n")
+        }),
+        MailRequest::Read(mail) => read(mail, id, pg).await.map(|mail| match mail {
+            Some(mail) => mail.view(),
+            None => "You have no such mail.".to_string(),
+        }),
+        MailRequest::Delete(mail) => delete(mail, id, pg).await.map(|deleted| {
+            if deleted {
+                format!("Mail #{mail} deleted.")
+            } else {
+                "You have no such mail.".to_string()
+            }
+        }),
+        MailRequest::Write { recipient, subject } => {
+            return write_mail(id, recipient, subject, pg)
+                .await
+                .unwrap_or_else(|e| {
+                    tracing::error!(%e, "Failed to check mail recipient");
+                    Outcome::from("Something went wrong; please try again later.".to_string())
+                });
+        }
+        MailRequest::Send {
+            recipient,
+            subject,
+            body,
+        } => {
+            return send_mail(id, recipient, subject, &body, pg)
+                .await
+                .unwrap_or_else(|e| {
+                    tracing::error!(%e, "Failed to send mail");
+                    Outcome::from(format!(
+                        "Something went wrong; your mail was not sent:\n\n{body}"
+                    ))
+                });
+        }
+    };
+
+    Outcome::from(result.unwrap_or_else(|e| {
+        tracing::error!(%e, "Failed to process mail request");
+        "Something went wrong; please try again later.".to_string()
+    }))
+}
+
+/// Works out who a mail is going to, and lets the player write it if they can
+/// send it to them. Everything is checked again once the mail is sent.
+async fn write_mail(
+    id: PlayerId,
+    recipient: MailRecipient,
+    subject: String,
+    pg: &PgPool,
+) -> Result<Outcome> {
+    let (name, subject) = match recipient {
+        MailRecipient::Player(name) => match find(&name, pg).await? {
+            Some(name) => (name, subject),
+            None => return Ok(Outcome::from(format!("There is no player named {name}."))),
+        },
+        MailRecipient::ReplyTo(mail) => match reply_to(mail, id, pg).await? {
+            Some(found) => found,
+            None => return Ok(Outcome::from("You have no such mail.".to_string())),
+        },
+    };
+
+    if ignores::is_ignoring(&name, id, pg).await? {
+        return Ok(Outcome::from(format!(
+            "{name} is not accepting mail from you."
+        )));
+    }
+
+    Ok(Outcome {
+        reply: format!("Writing to {name}: {subject}"),
+        notify: None,
+        write: Some(Purpose::Mail {
+            recipient: MailRecipient::Player(name),
+            subject,
+        }),
+    })
+}
+
+/// Sends a mail, working out who to send it to first if it is a reply. If it
+/// can't be sent, the body is shown again so it isn't lost.
+async fn send_mail(
+    id: PlayerId,
+    recipient: MailRecipient,
+    subject: String,
+    body: &str,
+    pg: &PgPool,
+) -> Result<Outcome> {
+    let (name, subject) = match recipient {
+        MailRecipient::Player(name) => (name, subject),
+        MailRecipient::ReplyTo(mail) => match reply_to(mail, id, pg).await? {
+            Some(found) => found,
+            None => return Ok(Outcome::from("You have no such mail.".to_string())),
+        },
+    };
+
+    if ignores::is_ignoring(&name, id, pg).await? {
+        return Ok(Outcome::from(format!(
+            "{name} is not accepting mail from you. Your mail was not sent:\n\n{body}"
+        )));
+    }
+
+    let outcome = match send(id, &name, &subject, body, pg).await? {
+        Some((recipient, name)) => Outcome {
+            reply: format!("Your mail to {name} has been sent."),
+            notify: Some(recipient),
+            write: None,
+        },
+        None => Outcome::from(format!(
+            "There is no player named {name}. Your mail was not sent:\n\n{body}"
+        )),
+    };
+
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reply_subjects() {
+        assert_eq!(reply_subject("Hello"), "Re: Hello");
+        assert_eq!(reply_subject("Re: Hello"), "Re: Hello");
+        assert_eq!(reply_subject("RE: Hello"), "RE: Hello");
+    }
+}
@@ -0,0 +1,201 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use flate2::{Compress, Compression, FlushCompress, Status};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Wraps the underlying socket of a Telnet connection so outgoing data can be
+/// switched over to MCCP2 (MUD Client Compression Protocol v2) mid-stream.
+///
+/// Until `start_compression` is called, this is a transparent passthrough. After
+/// that, every byte written is zlib-compressed; a sync flush is performed every
+/// time the stream is flushed, so the client always receives complete messages
+/// rather than waiting on the compressor to fill a block. Reading is never
+/// compressed under MCCP2, so reads always pass straight through.
+pub struct MccpStream<S> {
+    inner: S,
+    compressor: Option<Compress>,
+    // Compressed bytes which have not been written to the inner stream yet.
+    buffer: Vec<u8>,
+    // Whether anything has been compressed since the last sync flush.
+    dirty: bool,
+}
+
+impl<S> MccpStream<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            compressor: None,
+            buffer: Vec::new(),
+            dirty: false,
+        }
+    }
+
+    /// Compresses everything written from this point on. The caller is
+    /// responsible for flushing any uncompressed output (including the MCCP2
+    /// start sequence) before calling this.
+    pub fn start_compression(&mut self) {
+        if self.compressor.is_none() {
+            self.compressor = Some(Compress::new(Compression::default(), true));
+        }
+    }
+
+    pub fn is_compressing(&self) -> bool {
+        self.compressor.is_some()
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S: AsyncWrite + Unpin> MccpStream<S> {
+    /// Writes out as much of the compressed buffer as the inner stream accepts.
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.buffer.is_empty() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.buffer))?;
+
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+
+            self.buffer.drain(..n);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Runs `input` through the compressor, appending the output to `output` until
+/// all of the input has been consumed and the compressor has nothing left to
+/// emit for the given flush mode.
+fn compress(
+    compressor: &mut Compress,
+    mut input: &[u8],
+    output: &mut Vec<u8>,
+    flush: FlushCompress,
+) -> io::Result<()> {
+    loop {
+        output.reserve(input.len() + 64);
+
+        let before = compressor.total_in();
+        let status = compressor
+            .compress_vec(input, output, flush)
+            .map_err(io::Error::other)?;
+
+        let consumed = (compressor.total_in() - before) as usize;
+        input = input.get(consumed..).unwrap_or_default();
+
+        // If the compressor didn't fill the output buffer, it had nothing else
+        // to write.
+        if status == Status::StreamEnd || (input.is_empty() && output.len() < output.capacity()) {
+            return Ok(());
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for MccpStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for MccpStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        if this.compressor.is_none() {
+            return Pin::new(&mut this.inner).poll_write(cx, buf);
+        }
+
+        // Apply backpressure instead of growing the buffer forever when the
+        // client stops reading.
+        ready!(this.poll_drain(cx))?;
+
+        if let Some(compressor) = this.compressor.as_mut() {
+            compress(compressor, buf, &mut this.buffer, FlushCompress::None)?;
+            this.dirty = true;
+        }
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if this.dirty {
+            if let Some(compressor) = this.compressor.as_mut() {
+                compress(compressor, &[], &mut this.buffer, FlushCompress::Sync)?;
+            }
+
+            this.dirty = false;
+        }
+
+        ready!(this.poll_drain(cx))?;
+
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        // Properly end the compressed stream so the client knows the next
+        // bytes (if any) are uncompressed.
+        if let Some(mut compressor) = this.compressor.take() {
+            compress(
+                &mut compressor,
+                &[],
+                &mut this.buffer,
+                FlushCompress::Finish,
+            )?;
+            this.dirty = false;
+        }
+
+        ready!(this.poll_drain(cx))?;
+
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::{Decompress, FlushDecompress};
+
+    use super::*;
+
+    #[test]
+    fn sync_flush_round_trip() {
+        let text = "You are standing in an open field west of a white house. ".repeat(32);
+        let mut compressor = Compress::new(Compression::default(), true);
+        let mut output = Vec::new();
+
+        assert!(compress(
+            &mut compressor,
+            text.as_bytes(),
+            &mut output,
+            FlushCompress::None
+        )
+        .is_ok());
+        assert!(compress(&mut compressor, &[], &mut output, FlushCompress::Sync).is_ok());
+        assert!(output.len() < text.len());
+
+        let mut decompressor = Decompress::new(true);
+        let mut decompressed = Vec::with_capacity(text.len() * 2);
+
+        assert!(decompressor
+            .decompress_vec(&output, &mut decompressed, FlushDecompress::Sync)
+            .is_ok());
+        assert_eq!(decompressed, text.as_bytes());
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+
+use crate::{event::GameEvent, player::PlayerId, prompt::Prompt, response::Response};
+
+/// Collects the output for each player over the course of a tick, so it can be
+/// sent to their connection as a single batch once the tick is done.
+#[derive(Debug, Default)]
+pub struct Outbox {
+    pending: HashMap<PlayerId, Output>,
+}
+
+#[derive(Debug, Default)]
+struct Output {
+    events: Vec<GameEvent>,
+    // Only the latest prompt matters, as it always goes out last.
+    prompt: Option<Prompt>,
+}
+
+impl Outbox {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues an event for a player. Channel and chat responses are queued for
+    /// each of their recipients instead.
+    pub fn push(&mut self, id: PlayerId, event: GameEvent) {
+        match event {
+            GameEvent::Prompt(prompt) => {
+                self.pending.entry(id).or_default().prompt = Some(prompt);
+            }
+            GameEvent::Command(
+                Response::Channel(ref recipients, _) | Response::Chat(ref recipients, _),
+            ) => {
+                for recipient in recipients {
+                    self.pending
+                        .entry(*recipient)
+                        .or_default()
+                        .events
+                        .push(event.clone());
+                }
+            }
+            event => self.pending.entry(id).or_default().events.push(event),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Takes everything queued so far, with one batch of events per player.
+    /// The prompt, if there is one, is always the last event in a batch.
+    pub fn drain(&mut self) -> Vec<(PlayerId, Vec<GameEvent>)> {
+        self.pending
+            .drain()
+            .map(|(id, mut output)| {
+                if let Some(prompt) = output.prompt {
+                    output.events.push(GameEvent::Prompt(prompt));
+                }
+
+                (id, output.events)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::*;
+    use crate::player::Player;
+
+    fn prompt() -> Prompt {
+        Prompt::from(&Player::new(1, IpAddr::from([127, 0, 0, 1])))
+    }
+
+    #[test]
+    fn prompt_is_last() {
+        let mut outbox = Outbox::new();
+
+        outbox.push(1, GameEvent::Command(Response::client_message("first")));
+        outbox.push(1, GameEvent::Prompt(prompt()));
+        outbox.push(1, GameEvent::Command(Response::client_message("second")));
+        outbox.push(1, GameEvent::Prompt(prompt()));
+
+        let batches = outbox.drain();
+        let events = batches.first().map(|(_, events)| events);
+
+        assert_eq!(events.map(Vec::len), Some(3));
+        assert!(matches!(
+            events.and_then(|events| events.last()),
+            Some(GameEvent::Prompt(_))
+        ));
+        assert!(outbox.is_empty());
+    }
+
+    #[test]
+    fn broadcasts_reach_each_recipient() {
+        let mut outbox = Outbox::new();
+
+        outbox.push(
+            1,
+            GameEvent::Command(Response::Channel(vec![1, 2, 3], "Hello!".to_string())),
+        );
+
+        let mut batches = outbox.drain();
+        batches.sort_by_key(|(id, _)| *id);
+
+        assert_eq!(
+            batches.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert!(batches.iter().all(|(_, events)| events.len() == 1));
+    }
+}
@@ -0,0 +1,270 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+use crate::{
+    event::{Event, GameEvent},
+    response::Response,
+};
+
+/// What happens when a peer's queue is full, which means the client has stopped
+/// reading (or can't keep up with) its output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    // Make room by dropping the oldest output which isn't critical.
+    #[default]
+    DropOldest,
+    // Disconnect the peer; they are treated as link-dead.
+    Disconnect,
+}
+
+/// The outcome of queueing an event for a peer.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Delivery {
+    Queued,
+    // The queue was full, so an older event was dropped to make room.
+    DroppedOldest,
+    // The queue was full of critical events, so the new event was dropped.
+    DroppedNew,
+    // The queue was full, so the peer is being disconnected.
+    Disconnected,
+    // The peer has already gone away.
+    Closed,
+}
+
+struct State {
+    events: VecDeque<Event>,
+    closed: bool,
+    dropped: u64,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    notify: Notify,
+}
+
+/// Creates a bounded queue of events for a single peer. This works like a
+/// channel, except a full queue never blocks the sender; the overflow policy
+/// decides what gives instead.
+pub fn channel(capacity: usize, policy: OverflowPolicy) -> (PeerSender, PeerReceiver) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            events: VecDeque::with_capacity(capacity),
+            closed: false,
+            dropped: 0,
+        }),
+        capacity,
+        policy,
+        notify: Notify::new(),
+    });
+
+    (
+        PeerSender {
+            shared: Arc::clone(&shared),
+        },
+        PeerReceiver { shared },
+    )
+}
+
+/// The sending half of a peer queue, which is held by the broker.
+#[derive(Clone)]
+pub struct PeerSender {
+    shared: Arc<Shared>,
+}
+
+impl PeerSender {
+    pub fn send(&self, event: Event) -> Delivery {
+        let mut state = self.shared.state.lock();
+
+        if state.closed {
+            return Delivery::Closed;
+        }
+
+        let mut delivery = Delivery::Queued;
+
+        if state.events.len() >= self.shared.capacity {
+            match self.shared.policy {
+                OverflowPolicy::DropOldest => {
+                    let oldest = state.events.iter().position(|e| !is_critical(e));
+
+                    match oldest {
+                        Some(index) => {
+                            state.events.remove(index);
+                            state.dropped += 1;
+                            delivery = Delivery::DroppedOldest;
+                        }
+                        // Critical events are few and small, so they may go
+                        // over capacity rather than be lost.
+                        None if is_critical(&event) => {}
+                        None => {
+                            state.dropped += 1;
+                            return Delivery::DroppedNew;
+                        }
+                    }
+                }
+                OverflowPolicy::Disconnect => {
+                    // Pending output is thrown away, so the connection sees the
+                    // queue close as soon as it is done with its current write.
+                    // A client which stopped reading never finishes that
+                    // write, so the connection gives up on it after
+                    // `WRITE_TIMEOUT` instead.
+                    state.closed = true;
+                    state.events.clear();
+                    drop(state);
+
+                    self.shared.notify.notify_one();
+                    return Delivery::Disconnected;
+                }
+            }
+        }
+
+        state.events.push_back(event);
+        drop(state);
+
+        self.shared.notify.notify_one();
+        delivery
+    }
+
+    /// The number of events waiting to be written to the peer.
+    pub fn len(&self) -> usize {
+        self.shared.state.lock().events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity
+    }
+
+    /// The number of events dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.shared.state.lock().dropped
+    }
+
+    /// Whether both senders belong to the same queue.
+    pub fn same_queue(&self, other: &PeerSender) -> bool {
+        Arc::ptr_eq(&self.shared, &other.shared)
+    }
+}
+
+impl std::fmt::Debug for PeerSender {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PeerSender")
+            .field("len", &self.len())
+            .field("capacity", &self.capacity())
+            .finish()
+    }
+}
+
+/// The receiving half of a peer queue, which is held by the connection. The
+/// queue is closed when this is dropped.
+pub struct PeerReceiver {
+    shared: Arc<Shared>,
+}
+
+impl PeerReceiver {
+    /// Waits for the next event. Returns `None` once the queue is closed, such
+    /// as when the peer is disconnected for falling too far behind.
+    pub async fn recv(&self) -> Option<Event> {
+        loop {
+            {
+                let mut state = self.shared.state.lock();
+
+                if let Some(event) = state.events.pop_front() {
+                    return Some(event);
+                }
+
+                if state.closed {
+                    return None;
+                }
+            }
+
+            self.shared.notify.notified().await;
+        }
+    }
+}
+
+impl Drop for PeerReceiver {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock();
+        state.closed = true;
+        state.events.clear();
+    }
+}
+
+fn is_critical(event: &Event) -> bool {
+    matches!(event, Event::Game(_, event) if event.is_critical())
+}
+
+impl GameEvent {
+    /// Whether the event has to reach the client, even when it is falling
+    /// behind. Everything else is output which can be dropped.
+    pub fn is_critical(&self) -> bool {
+        match self {
+            GameEvent::Accepted(_)
+            | GameEvent::Replaced
+            | GameEvent::WrapWidth(_)
+            | GameEvent::LineInput(_)
+            | GameEvent::Command(Response::Close) => true,
+            GameEvent::Batch(events) => events.iter().any(GameEvent::is_critical),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(text: &str) -> Event {
+        Event::Game(1, GameEvent::Command(Response::client_message(text)))
+    }
+
+    #[test]
+    fn drop_oldest() {
+        let (tx, _rx) = channel(2, OverflowPolicy::DropOldest);
+
+        assert_eq!(
+            tx.send(Event::Game(1, GameEvent::Replaced)),
+            Delivery::Queued
+        );
+        assert_eq!(tx.send(message("first")), Delivery::Queued);
+        assert_eq!(tx.send(message("second")), Delivery::DroppedOldest);
+        assert_eq!(tx.len(), 2);
+        assert_eq!(tx.dropped(), 1);
+
+        // Critical events push output out, and once only they are left, new
+        // output is dropped instead.
+        assert_eq!(
+            tx.send(Event::Game(1, GameEvent::Replaced)),
+            Delivery::DroppedOldest
+        );
+        assert_eq!(tx.send(message("third")), Delivery::DroppedNew);
+        assert_eq!(tx.len(), 2);
+    }
+
+    #[test]
+    fn disconnect() {
+        let (tx, _rx) = channel(1, OverflowPolicy::Disconnect);
+
+        assert_eq!(tx.send(message("first")), Delivery::Queued);
+        assert_eq!(tx.send(message("second")), Delivery::Disconnected);
+        assert_eq!(tx.send(message("third")), Delivery::Closed);
+        assert!(tx.is_empty());
+    }
+
+    #[test]
+    fn receiver_closes_on_drop() {
+        let (tx, rx) = channel(1, OverflowPolicy::DropOldest);
+        drop(rx);
+
+        assert_eq!(tx.send(message("first")), Delivery::Closed);
+    }
+}
@@ -5,7 +5,10 @@ use sqlx::PgPool;
 use crate::{
     account::Account,
     entity::{Entity, EntityId},
+    error::Result,
+    ignores::Ignored,
     quickmap::QuickMapKey,
+    searchable::Searchable,
     vec3::Vec3,
 };
 
@@ -49,8 +52,26 @@ pub struct Player {
     pub level: i32,
     pub brief: bool,
     pub afk: bool,
+    // The width to wrap output at for clients which don't support NAWS. A
+    // width of 0 disables wrapping.
+    pub wrap_width: i32,
+    // The names of the chat channels the player is subscribed to. This is
+    // `None` until the player first enters the world, when they are subscribed
+    // to the channels which are joined automatically.
+    pub channels: Option<Vec<String>>,
+    // The name of the last player who sent them a tell, which `reply` answers.
+    pub reply_to: Option<String>,
+    // How much unread mail the player had when they logged in.
+    pub unread_mail: i64,
+    // The players whose messages this player doesn't want to see, shared by
+    // every character on their account.
+    pub ignores: Vec<Ignored>,
     pub dirty: bool,
     pub seen: bool,
+    // The second (of server uptime) the players connection dropped, if it has.
+    // Link-dead players stay in the world until they reconnect or the grace
+    // period runs out.
+    pub linkdead: Option<u64>,
 }
 
 impl Player {
@@ -71,13 +92,35 @@ impl Player {
             level: 1,
             brief: false,
             afk: false,
+            wrap_width: 80,
+            channels: None,
+            reply_to: None,
+            unread_mail: 0,
+            ignores: Vec::new(),
             dirty: false,
             seen: false,
+            linkdead: None,
         }
     }
 
-    pub async fn save(&mut self, pg: PgPool) {
-        if sqlx::query!(
+    /// Whether the player is subscribed to a chat channel.
+    pub fn in_channel(&self, name: &str) -> bool {
+        self.channels
+            .iter()
+            .flatten()
+            .any(|channel| channel == name)
+    }
+
+    /// Whether the player is ignoring messages from an account. Staff can't be
+    /// ignored, so moderators can always reach players.
+    pub fn is_ignoring(&self, sender: &Account) -> bool {
+        !sender.is_moderator() && self.ignores.iter().any(|i| i.account_id == sender.id)
+    }
+
+    /// Writes the player to the database, and marks them as saved if it
+    /// succeeds.
+    pub async fn save(&mut self, pg: PgPool) -> Result<()> {
+        sqlx::query!(
             "update players
             set position = $1,
                 health = $2,
@@ -87,8 +130,10 @@ impl Player {
                 xp = $6,
                 level = $7,
                 brief = $8,
-                afk = $9
-            where id = $10",
+                afk = $9,
+                wrap_width = $10,
+                channels = $11
+            where id = $12",
             &self.position.as_vec(),
             self.health,
             self.max_health,
@@ -98,16 +143,22 @@ impl Player {
             self.level,
             self.brief,
             self.afk,
+            self.wrap_width,
+            self.channels.as_deref(),
             self.id
         )
         .execute(&pg)
-        .await
-        .is_ok()
-        {
-            self.dirty = false;
-        } else {
-            tracing::error!("Failed to save player {} to the database.", self.id);
-        }
+        .await?;
+
+        self.dirty = false;
+
+        Ok(())
+    }
+}
+
+impl<'a> Searchable for &'a Player {
+    fn search_key(&self) -> &str {
+        &self.name
     }
 }
 
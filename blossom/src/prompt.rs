@@ -1,12 +1,14 @@
 use std::fmt::Display;
 
 use iridescent::Styled;
+use serde::Serialize;
 
 use crate::{player::Player, theme};
 
 /// Represents the chat prompt which appears as the final line of every client
 /// message. The prompt is configurable, so we represent it with a struct that
 /// implements `StyledString`.
+#[derive(Clone, Debug, Serialize)]
 pub struct Prompt {
     health: Option<i32>,
     max_health: Option<i32>,
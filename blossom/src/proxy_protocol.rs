@@ -0,0 +1,161 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::error::{Error, ErrorType, Result};
+
+/// Every v2 header starts with this signature, which can't be mistaken for the
+/// start of a v1 header (or any other protocol).
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// The longest a v1 header can be, including the trailing CRLF.
+const V1_MAX_LENGTH: usize = 107;
+
+/// Reads a PROXY protocol (v1 or v2) header from the start of a stream and
+/// returns the address of the client the proxy accepted the connection from.
+///
+/// Returns `None` when the proxy doesn't know the address, or when the
+/// connection was made by the proxy itself (such as a health check), in which
+/// case the address of the socket should be used as-is.
+///
+/// Only the header is read, so the stream can be handed off to the Telnet
+/// codec or WebSocket handshake afterwards.
+pub async fn read_header<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Option<SocketAddr>> {
+    // Both versions are at least this long, so this never reads past the
+    // header.
+    let mut prefix = [0; 12];
+    stream.read_exact(&mut prefix).await?;
+
+    if prefix == V2_SIGNATURE {
+        let mut fixed = [0; 4];
+        stream.read_exact(&mut fixed).await?;
+
+        let [version_command, family, length @ ..] = fixed;
+        let mut addresses = vec![0; usize::from(u16::from_be_bytes(length))];
+        stream.read_exact(&mut addresses).await?;
+
+        return parse_v2(version_command, family, &addresses);
+    }
+
+    if !prefix.starts_with(b"PROXY ") {
+        return Err(invalid("Missing PROXY protocol header"));
+    }
+
+    // The v1 header is a single line, which we read a byte at a time so none
+    // of the data after it is consumed.
+    let mut line = prefix.to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LENGTH {
+            return Err(invalid("PROXY protocol header is too long"));
+        }
+
+        line.push(stream.read_u8().await?);
+    }
+
+    parse_v1(&line)
+}
+
+/// Parses a v1 header, such as `PROXY TCP4 192.0.2.1 198.51.100.1 56324 5000`.
+fn parse_v1(line: &[u8]) -> Result<Option<SocketAddr>> {
+    let line = std::str::from_utf8(line)
+        .map_err(|_| invalid("PROXY protocol header is not valid ASCII"))?
+        .trim_end_matches("\r\n");
+    let mut parts = line.split(' ');
+
+    match (parts.next(), parts.next()) {
+        (Some("PROXY"), Some("UNKNOWN")) => Ok(None),
+        (Some("PROXY"), Some("TCP4" | "TCP6")) => {
+            let source = parts.next().and_then(|ip| ip.parse::<IpAddr>().ok());
+            let _destination = parts.next();
+            let port = parts.next().and_then(|port| port.parse::<u16>().ok());
+
+            match (source, port) {
+                (Some(ip), Some(port)) => Ok(Some(SocketAddr::new(ip, port))),
+                _ => Err(invalid("Malformed PROXY protocol header")),
+            }
+        }
+        _ => Err(invalid("Malformed PROXY protocol header")),
+    }
+}
+
+/// Parses the remainder of a v2 header after the signature.
+fn parse_v2(version_command: u8, family: u8, addresses: &[u8]) -> Result<Option<SocketAddr>> {
+    if version_command >> 4 != 2 {
+        return Err(invalid("Unsupported PROXY protocol version"));
+    }
+
+    match version_command & 0x0F {
+        // LOCAL: the proxy made the connection itself.
+        0 => return Ok(None),
+        // PROXY: the connection was made on behalf of a client.
+        1 => {}
+        _ => return Err(invalid("Unsupported PROXY protocol command")),
+    }
+
+    let port = |offset: usize| {
+        addresses
+            .get(offset..offset + 2)
+            .and_then(|bytes| bytes.try_into().ok())
+            .map(u16::from_be_bytes)
+    };
+
+    // The low bits of the family are the transport protocol, which we don't
+    // care about.
+    let address = match family >> 4 {
+        // AF_INET: 4 byte source and destination addresses, then the ports.
+        1 => addresses
+            .get(..4)
+            .and_then(|bytes| <[u8; 4]>::try_from(bytes).ok())
+            .zip(port(8))
+            .map(|(ip, port)| SocketAddr::new(IpAddr::V4(Ipv4Addr::from(ip)), port)),
+        // AF_INET6: 16 byte source and destination addresses, then the ports.
+        2 => addresses
+            .get(..16)
+            .and_then(|bytes| <[u8; 16]>::try_from(bytes).ok())
+            .zip(port(32))
+            .map(|(ip, port)| SocketAddr::new(IpAddr::V6(Ipv6Addr::from(ip)), port)),
+        // AF_UNSPEC or AF_UNIX: there is no address we could use.
+        _ => return Ok(None),
+    };
+
+    address
+        .map(Some)
+        .ok_or_else(|| invalid("Malformed PROXY protocol header"))
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorType::Io, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_v1_tcp4() {
+        let address = parse_v1(b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 5000\r\n");
+
+        assert!(matches!(
+            address,
+            Ok(Some(addr)) if addr == SocketAddr::from(([192, 0, 2, 1], 56324))
+        ));
+    }
+
+    #[test]
+    fn parse_v1_unknown() {
+        assert!(matches!(parse_v1(b"PROXY UNKNOWN\r\n"), Ok(None)));
+        assert!(parse_v1(b"PROXY TCP4 nonsense\r\n").is_err());
+    }
+
+    #[test]
+    fn parse_v2_tcp4() {
+        let addresses = [192, 0, 2, 1, 198, 51, 100, 1, 0xDC, 0x04, 0x13, 0x88];
+        let address = parse_v2(0x21, 0x11, &addresses);
+
+        assert!(matches!(
+            address,
+            Ok(Some(addr)) if addr == SocketAddr::from(([192, 0, 2, 1], 56324))
+        ));
+        assert!(matches!(parse_v2(0x20, 0x00, &[]), Ok(None)));
+    }
+}
@@ -0,0 +1,246 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+
+/// A token bucket which holds up to `capacity` tokens and regains `rate`
+/// tokens per second. Every line of input costs one token, so a client can
+/// send a short burst of commands, but not keep sending faster than the rate.
+#[derive(Debug)]
+pub struct TokenBucket {
+    capacity: f64,
+    rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: u32, rate: f64) -> Self {
+        Self {
+            capacity: f64::from(capacity),
+            rate,
+            tokens: f64::from(capacity),
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Takes a token if there is one available.
+    pub fn try_take(&mut self) -> bool {
+        self.try_take_at(Instant::now())
+    }
+
+    fn try_take_at(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.capacity);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// How long a client has to go without being warned for one of its warnings to
+/// be forgiven, so a player who floods now and then over a long session isn't
+/// treated as a repeat offender.
+const WARNING_DECAY: Duration = Duration::from_secs(600);
+
+/// What flooding clients are told when they are warned.
+pub const FLOOD_WARNING: &str =
+    "You are sending commands too quickly. Slow down, or you will be disconnected.";
+
+/// What flooding clients are told when they are disconnected.
+pub const FLOOD_DISCONNECT: &str = "You have been disconnected for flooding.";
+
+/// What to do with a line of input, as decided by the `RateLimiter`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict {
+    // The input is within the limit.
+    Allow,
+    // The client just started flooding; the input is dropped and the player
+    // is warned.
+    Warn,
+    // The client is still flooding after being warned; the input is dropped.
+    Drop,
+    // The client has been warned too many times and should be disconnected.
+    Disconnect,
+}
+
+/// Limits how fast a single connection can send input. Each time a client
+/// runs out of tokens it is warned, and further input is dropped until the
+/// bucket refills. Clients which keep flooding after `max_warnings` warnings
+/// are disconnected. Warnings wear off, one for every `WARNING_DECAY` without
+/// a new one.
+#[derive(Debug)]
+pub struct RateLimiter {
+    bucket: TokenBucket,
+    max_warnings: u32,
+    warnings: u32,
+    last_warning: Option<Instant>,
+    flooding: bool,
+}
+
+impl RateLimiter {
+    pub fn new(burst: u32, rate: f64, max_warnings: u32) -> Self {
+        Self {
+            bucket: TokenBucket::new(burst, rate),
+            max_warnings,
+            warnings: 0,
+            last_warning: None,
+            flooding: false,
+        }
+    }
+
+    /// Decides what to do with the next line of input.
+    pub fn check(&mut self) -> Verdict {
+        let allowed = self.bucket.try_take();
+        self.verdict(allowed, Instant::now())
+    }
+
+    fn verdict(&mut self, allowed: bool, now: Instant) -> Verdict {
+        if allowed {
+            self.flooding = false;
+            return Verdict::Allow;
+        }
+
+        if self.flooding {
+            return Verdict::Drop;
+        }
+
+        self.flooding = true;
+
+        if let Some(last) = self.last_warning {
+            let quiet = now.saturating_duration_since(last);
+            let forgiven = quiet.as_secs() / WARNING_DECAY.as_secs();
+            self.warnings = self
+                .warnings
+                .saturating_sub(u32::try_from(forgiven).unwrap_or(u32::MAX));
+        }
+
+        self.warnings += 1;
+        self.last_warning = Some(now);
+
+        if self.warnings > self.max_warnings {
+            Verdict::Disconnect
+        } else {
+            Verdict::Warn
+        }
+    }
+}
+
+/// Counts open connections per IP address, so a single address can't hold
+/// open an unbounded number of connections.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionCounter {
+    counts: Arc<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+impl ConnectionCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts a new connection from the address, unless it already has `max`
+    /// open connections. The connection is counted until the returned guard
+    /// is dropped. A `max` of 0 means there is no limit.
+    pub fn try_acquire(&self, ip: IpAddr, max: usize) -> Option<ConnectionGuard> {
+        let mut counts = self.counts.lock();
+        let count = counts.entry(ip).or_default();
+
+        if max > 0 && *count >= max {
+            return None;
+        }
+
+        *count += 1;
+
+        Some(ConnectionGuard {
+            ip,
+            counts: Arc::clone(&self.counts),
+        })
+    }
+}
+
+/// Keeps a connection counted in its `ConnectionCounter` while it is alive.
+#[derive(Debug)]
+pub struct ConnectionGuard {
+    ip: IpAddr,
+    counts: Arc<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let mut counts = self.counts.lock();
+
+        if let Some(count) = counts.get_mut(&self.ip) {
+            *count = count.saturating_sub(1);
+
+            if *count == 0 {
+                counts.remove(&self.ip);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::Ipv4Addr, time::Duration};
+
+    use super::*;
+
+    #[test]
+    fn bucket_refills() {
+        let mut bucket = TokenBucket::new(2, 1.0);
+        let start = bucket.last_refill;
+
+        assert!(bucket.try_take_at(start));
+        assert!(bucket.try_take_at(start));
+        assert!(!bucket.try_take_at(start));
+        assert!(bucket.try_take_at(start + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn warn_then_drop_then_disconnect() {
+        let mut limiter = RateLimiter::new(1, 1.0, 1);
+        let now = Instant::now();
+
+        assert_eq!(limiter.verdict(false, now), Verdict::Warn);
+        assert_eq!(limiter.verdict(false, now), Verdict::Drop);
+        assert_eq!(limiter.verdict(true, now), Verdict::Allow);
+        assert_eq!(limiter.verdict(false, now), Verdict::Disconnect);
+    }
+
+    #[test]
+    fn warnings_wear_off() {
+        let mut limiter = RateLimiter::new(1, 1.0, 1);
+        let now = Instant::now();
+
+        assert_eq!(limiter.verdict(false, now), Verdict::Warn);
+        assert_eq!(limiter.verdict(true, now), Verdict::Allow);
+
+        // A burst after a quiet spell is only warned about again.
+        let later = now + WARNING_DECAY;
+        assert_eq!(limiter.verdict(false, later), Verdict::Warn);
+        assert_eq!(limiter.verdict(true, later), Verdict::Allow);
+        assert_eq!(limiter.verdict(false, later), Verdict::Disconnect);
+    }
+
+    #[test]
+    fn connection_limit() {
+        let counter = ConnectionCounter::new();
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+        let first = counter.try_acquire(ip, 1);
+        assert!(first.is_some());
+        assert!(counter.try_acquire(ip, 1).is_none());
+
+        drop(first);
+        assert!(counter.try_acquire(ip, 1).is_some());
+    }
+}
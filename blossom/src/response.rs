@@ -17,6 +17,23 @@ pub enum Response {
     // A response that is sent to a group of clients; represented as an array of
     // player IDs.
     Channel(Vec<PlayerId>, String),
+    // A view of the players surroundings, such as after looking or moving.
+    Room(String),
+    // A chat message that is sent to a group of clients.
+    Chat(Vec<PlayerId>, Chat),
+}
+
+/// A chat message, along with the channel it was sent on and who sent it.
+/// Text clients only see the formatted `text`; the rest is there for
+/// structured clients, such as the web client.
+#[derive(Clone, Debug)]
+pub struct Chat {
+    pub channel: String,
+    pub sender: PlayerId,
+    // The name of the sender.
+    pub name: String,
+    // The full, formatted message.
+    pub text: String,
 }
 
 impl std::fmt::Display for Response {
@@ -35,6 +52,18 @@ impl std::fmt::Display for Response {
                     .join(", "),
                 s
             ),
+            Response::Room(s) => write!(f, "Room {s}"),
+            Response::Chat(players, chat) => write!(
+                f,
+                "Chat {} [{}] {}",
+                players
+                    .iter()
+                    .map(std::string::ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                chat.channel,
+                chat.text
+            ),
         }
     }
 }
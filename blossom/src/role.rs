@@ -1,3 +1,5 @@
+use std::str::FromStr;
+
 /// Represents a permissions-based role that an account can hold.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Role {
@@ -7,15 +9,25 @@ pub enum Role {
     Player,
 }
 
+impl FromStr for Role {
+    type Err = String;
+
+    /// Parses a role by name, failing if there is no such role.
+    fn from_str(role: &str) -> Result<Self, Self::Err> {
+        match role {
+            "admin" => Ok(Role::Admin),
+            "moderator" => Ok(Role::Moderator),
+            "builder" => Ok(Role::Builder),
+            "player" => Ok(Role::Player),
+            _ => Err(format!("Invalid role: {role}")),
+        }
+    }
+}
+
+// Roles from the database are always valid, so an unknown one is a bug.
 impl From<String> for Role {
     fn from(role: String) -> Self {
-        match role.as_str() {
-            "admin" => Role::Admin,
-            "moderator" => Role::Moderator,
-            "builder" => Role::Builder,
-            "player" => Role::Player,
-            _ => panic!("Invalid role: {role}"),
-        }
+        role.parse().unwrap_or_else(|e: String| panic!("{e}"))
     }
 }
 
@@ -15,6 +15,7 @@ use crate::{
 #[derive(Debug)]
 pub struct Room {
     pub entity_id: EntityId,
+    pub area: String,
     pub name: String,
     pub position: Vec3,
     pub description: String,
@@ -36,6 +37,7 @@ impl RoomBuilder {
     pub fn build(self, id: EntityId) -> Room {
         Room {
             entity_id: id,
+            area: self.area,
             name: self.name,
             position: self.position,
             description: self.description,
@@ -106,7 +108,11 @@ impl Room {
             .iter()
             .filter_map(|p| {
                 if p.position == player.position && p.id != id {
-                    Some(p.name.clone())
+                    if p.linkdead.is_some() {
+                        Some(format!("{} (link-dead)", p.name))
+                    } else {
+                        Some(p.name.clone())
+                    }
                 } else {
                     None
                 }
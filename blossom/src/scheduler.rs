@@ -0,0 +1,308 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+};
+
+use crate::{guard::catch_panic, world::World};
+
+/// Identifies a scheduled task, so it can be cancelled.
+pub type TaskId = u64;
+
+type TaskFn = Box<dyn FnMut(&mut World) + Send + Sync>;
+
+/// A closure waiting to run on the game loop.
+struct Task {
+    id: TaskId,
+    name: &'static str,
+    // The tick the task runs on.
+    due: u64,
+    // How many ticks to wait between runs, for tasks which repeat.
+    interval: Option<u64>,
+    func: TaskFn,
+}
+
+/// Runs closures on the game loop after a delay, or on an interval. Tasks are
+/// kept in order of the tick they are due on, so each tick only looks at the
+/// tasks which are due.
+///
+/// Use `World::after` and `World::every` to schedule tasks, rather than this
+/// directly.
+#[derive(Default)]
+pub struct Scheduler {
+    next_id: TaskId,
+    // The tick each task is due on, soonest first. Cancelled tasks are left in
+    // here, and skipped once they come up.
+    queue: BinaryHeap<Reverse<(u64, TaskId)>>,
+    tasks: HashMap<TaskId, Task>,
+    // The task which is running right now, as it is taken out of `tasks` while
+    // it runs.
+    running: Option<TaskId>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a task which runs on tick `due`, and then every `interval` ticks
+    /// after that if it has one.
+    pub fn add(
+        &mut self,
+        name: &'static str,
+        due: u64,
+        interval: Option<u64>,
+        func: TaskFn,
+    ) -> TaskId {
+        self.next_id += 1;
+
+        let task = Task {
+            id: self.next_id,
+            name,
+            due,
+            // A task which repeats every tick at most, so it can't run forever
+            // within a single one.
+            interval: interval.map(|interval| interval.max(1)),
+            func,
+        };
+        self.push(task);
+
+        self.next_id
+    }
+
+    /// Cancels a task. Returns false if there is no such task, such as when it
+    /// already ran.
+    pub fn cancel(&mut self, id: TaskId) -> bool {
+        if self.tasks.remove(&id).is_some() {
+            return true;
+        }
+
+        // A repeating task can cancel itself while it runs.
+        if self.running == Some(id) {
+            self.running = None;
+            return true;
+        }
+
+        false
+    }
+
+    /// How many tasks are waiting to run.
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// Describes each waiting task on a line of its own, soonest first, for
+    /// admins.
+    pub fn summary(&self, now: u64) -> String {
+        let mut tasks = self.tasks.values().collect::<Vec<_>>();
+        tasks.sort_by_key(|task| (task.due, task.id));
+
+        tasks
+            .iter()
+            .map(|task| {
+                let due = task.due.saturating_sub(now);
+                match task.interval {
+                    Some(interval) => format!(
+                        "\n  #{} {} (in {due} ticks, then every {interval})",
+                        task.id, task.name
+                    ),
+                    None => format!("\n  #{} {} (in {due} ticks)", task.id, task.name),
+                }
+            })
+            .collect()
+    }
+
+    fn push(&mut self, task: Task) {
+        self.queue.push(Reverse((task.due, task.id)));
+        self.tasks.insert(task.id, task);
+    }
+
+    /// Takes the next task which is due by tick `now`.
+    fn pop_due(&mut self, now: u64) -> Option<Task> {
+        while let Some(Reverse((due, id))) = self.queue.peek().copied() {
+            if due > now {
+                return None;
+            }
+
+            self.queue.pop();
+
+            if let Some(task) = self.tasks.remove(&id) {
+                self.running = Some(id);
+                return Some(task);
+            }
+        }
+
+        None
+    }
+
+    /// Puts a task back after it ran, if it repeats and didn't cancel itself.
+    fn finish(&mut self, mut task: Task, now: u64) {
+        if self.running.take() != Some(task.id) {
+            return;
+        }
+
+        if let Some(interval) = task.interval {
+            task.due = now + interval;
+            self.push(task);
+        }
+    }
+}
+
+impl World {
+    /// Runs a task once, after at least `delay` has passed.
+    pub fn after(
+        &mut self,
+        name: &'static str,
+        delay: std::time::Duration,
+        func: impl FnOnce(&mut World) + Send + Sync + 'static,
+    ) -> TaskId {
+        let due = self.timer.count + self.timer.ticks(delay).max(1);
+
+        let mut func = Some(func);
+        self.scheduler.add(
+            name,
+            due,
+            None,
+            Box::new(move |world| {
+                if let Some(func) = func.take() {
+                    func(world);
+                }
+            }),
+        )
+    }
+
+    /// Runs a task every `interval` ticks, starting `interval` ticks from now,
+    /// until it is cancelled.
+    pub fn every(
+        &mut self,
+        name: &'static str,
+        interval: u64,
+        func: impl FnMut(&mut World) + Send + Sync + 'static,
+    ) -> TaskId {
+        let interval = interval.max(1);
+        let due = self.timer.count + interval;
+
+        self.scheduler
+            .add(name, due, Some(interval), Box::new(func))
+    }
+
+    /// Cancels a scheduled task. Returns false if there is no such task.
+    pub fn cancel(&mut self, id: TaskId) -> bool {
+        self.scheduler.cancel(id)
+    }
+
+    /// Runs every task which is due this tick. Tasks are taken out of the
+    /// scheduler while they run, so they can schedule or cancel other tasks.
+    /// A task which panics is dropped, even if it repeats.
+    pub(crate) fn run_tasks(&mut self) {
+        let now = self.timer.count;
+
+        while let Some(mut task) = self.scheduler.pop_due(now) {
+            if let Err(message) = catch_panic(|| (task.func)(self)) {
+                self.scheduler.running = None;
+                self.panicked(&format!("Task {}", task.name), &message);
+                continue;
+            }
+
+            self.scheduler.finish(task, now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use super::*;
+
+    fn counter() -> (
+        Arc<AtomicU64>,
+        impl FnMut(&mut World) + Send + Sync + 'static,
+    ) {
+        let count = Arc::new(AtomicU64::new(0));
+        let handle = Arc::clone(&count);
+
+        (count, move |_: &mut World| {
+            handle.fetch_add(1, Ordering::Relaxed);
+        })
+    }
+
+    fn run_ticks(world: &mut World, ticks: u64) {
+        for _ in 0..ticks {
+            world.timer.count += 1;
+            world.run_tasks();
+        }
+    }
+
+    #[test]
+    fn runs_after_delay() {
+        let mut world = World::new();
+        let (count, mut func) = counter();
+
+        // 100ms is two ticks at the default 20 ticks per second.
+        world.after("test", Duration::from_millis(100), move |world| func(world));
+
+        run_ticks(&mut world, 1);
+        assert_eq!(count.load(Ordering::Relaxed), 0);
+
+        run_ticks(&mut world, 5);
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+        assert!(world.scheduler.is_empty());
+    }
+
+    #[test]
+    fn repeats_until_cancelled() {
+        let mut world = World::new();
+        let (count, func) = counter();
+
+        let id = world.every("test", 3, func);
+
+        run_ticks(&mut world, 9);
+        assert_eq!(count.load(Ordering::Relaxed), 3);
+
+        assert!(world.cancel(id));
+        assert!(!world.cancel(id));
+
+        run_ticks(&mut world, 9);
+        assert_eq!(count.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn tasks_can_cancel_themselves() {
+        let mut world = World::new();
+        let (count, mut func) = counter();
+
+        // Task IDs start at 1, so this is the task's own ID.
+        world.every("test", 1, move |world| {
+            func(world);
+            world.cancel(1);
+        });
+
+        run_ticks(&mut world, 5);
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+        assert!(world.scheduler.is_empty());
+    }
+
+    #[test]
+    fn panicking_tasks_are_dropped() {
+        let mut world = World::new();
+        let (count, func) = counter();
+
+        world.every("broken", 1, |_| panic!("oops"));
+        world.every("test", 1, func);
+
+        run_ticks(&mut world, 3);
+        assert_eq!(count.load(Ordering::Relaxed), 3);
+        assert_eq!(world.scheduler.len(), 1);
+        assert!(world.scheduler.running.is_none());
+    }
+}
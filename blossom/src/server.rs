@@ -1,22 +1,51 @@
+use std::{net::SocketAddr, sync::Arc};
+
 use flume::unbounded;
-use tokio::net::TcpListener;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::TlsAcceptor;
 use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
 
 use crate::{
     broker::Broker,
     config::Config,
-    connection_handler::connection_loop,
+    connection_handler::{connection_loop, with_timeout, Shared},
     database::Database,
     error::Result,
     event::Event,
     game::Game,
     logging::{Action, Logger},
+    proxy_protocol,
+    rate_limit::ConnectionCounter,
+    status::ServerStatus,
+    tls,
     world::World,
 };
 
 pub enum StreamType {
     Telnet,
     WebSocket,
+    SecureTelnet(TlsAcceptor),
+    SecureWebSocket(TlsAcceptor),
+}
+
+impl std::fmt::Display for StreamType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StreamType::Telnet => write!(f, "Telnet"),
+            StreamType::WebSocket => write!(f, "WebSocket"),
+            StreamType::SecureTelnet(_) => write!(f, "secure Telnet"),
+            StreamType::SecureWebSocket(_) => write!(f, "secure WebSocket"),
+        }
+    }
+}
+
+/// Accepts a connection on an optional listener. If there is no listener, this
+/// never resolves, so disabled listeners can sit in a `select!` unnoticed.
+async fn accept(listener: Option<&TcpListener>) -> std::io::Result<(TcpStream, SocketAddr)> {
+    match listener {
+        Some(listener) => listener.accept().await,
+        None => std::future::pending().await,
+    }
 }
 
 /// Entry point of every Blossom game.
@@ -43,12 +72,30 @@ impl Server {
         // Loads the `config.toml` file in the /game directory. We also set the
         // environment variable for our database here, which means we MUST
         // create the state AFTER these functions.
-        let config = Config::load().await?;
+        let config = Arc::new(Config::load().await?);
 
         // Creates our connection listener
         let telnet_listener = TcpListener::bind(config.telnet_addr()).await?;
         let websocket_listener = TcpListener::bind(config.websocket_addr()).await?;
 
+        // Creates our secure listeners, if TLS is enabled. We fail early on a
+        // bad certificate or key, rather than on the first secure connection.
+        let (acceptor, secure_telnet_listener, secure_websocket_listener) = if config.tls.enabled {
+            let acceptor = tls::acceptor(&config.tls)?;
+            let telnet = TcpListener::bind(config.telnet_tls_addr()).await?;
+            let websocket = TcpListener::bind(config.websocket_tls_addr()).await?;
+
+            tracing::info!(
+                "Server listening on {} (Telnet over TLS) and {} (WebSocket over TLS)",
+                config.telnet_tls_addr(),
+                config.websocket_tls_addr()
+            );
+
+            (Some(acceptor), Some(telnet), Some(websocket))
+        } else {
+            (None, None, None)
+        };
+
         if config.web.enabled {
             let pg = db.clone();
             tokio::spawn(async move {
@@ -68,11 +115,25 @@ impl Server {
         let _logger_handle = Logger::start(db.clone(), rx_logger).await?;
 
         // Starts the broker loop
-        let _broker_handle = Broker::start(db.clone(), rx_broker, tx_game).await?;
+        let _broker_handle =
+            Broker::start(db.clone(), rx_broker, tx_game, tx_logger.clone()).await?;
+
+        // Create the server status snapshot, which the game loop updates and
+        // connections read from when answering MSSP requests
+        let status = ServerStatus::new(&config).handle();
 
         // Create the world and starts the game loop on its own (blocking)
         // thread
-        Game::run(world, &config, rx_game, tx_broker.clone());
+        Game::run(world, &config, status.clone(), rx_game, tx_broker.clone());
+
+        let shared = Shared {
+            pg: db.clone(),
+            config: Arc::clone(&config),
+            status,
+            connections: ConnectionCounter::new(),
+            tx_broker,
+            tx_logger,
+        };
 
         tracing::info!(
             "Server listening on {} (Telnet) and {} (WebSocket)",
@@ -81,30 +142,52 @@ impl Server {
         );
 
         loop {
-            let pg = db.clone();
-            let tx_broker = tx_broker.clone();
-            let tx_logger = tx_logger.clone();
+            // Each listener accepts connections of its own stream type, and
+            // may expect a PROXY protocol header from a load balancer.
+            let (stream_type, proxy_protocol, (mut stream, addr)) = tokio::select! {
+                Ok(accepted) = telnet_listener.accept() => {
+                    (StreamType::Telnet, config.game.telnet_proxy_protocol, accepted)
+                }
+                Ok(accepted) = websocket_listener.accept() => {
+                    (StreamType::WebSocket, config.game.websocket_proxy_protocol, accepted)
+                }
+                Ok(accepted) = accept(secure_telnet_listener.as_ref()) => {
+                    let Some(acceptor) = acceptor.clone() else { continue };
+                    (StreamType::SecureTelnet(acceptor), config.tls.telnet_proxy_protocol, accepted)
+                }
+                Ok(accepted) = accept(secure_websocket_listener.as_ref()) => {
+                    let Some(acceptor) = acceptor.clone() else { continue };
+                    (StreamType::SecureWebSocket(acceptor), config.tls.websocket_proxy_protocol, accepted)
+                }
+            };
 
-            tokio::select! {
-                Ok((stream, addr)) = telnet_listener.accept() => {
-                    tokio::spawn(async move {
-                        tracing::info!("New connection from {}", addr);
+            let shared = shared.clone();
 
-                        if let Err(e) = connection_loop(StreamType::Telnet, addr, stream, pg, tx_broker, tx_logger).await {
-                            tracing::error!(%e, "Failed to establish Telnet stream");
+            tokio::spawn(async move {
+                // The header has to be read before anything else, as it comes
+                // before the TLS or WebSocket handshake.
+                let addr = if proxy_protocol {
+                    let header = proxy_protocol::read_header(&mut stream);
+
+                    match with_timeout("PROXY header", header).await {
+                        Ok(Some(client_addr)) => client_addr,
+                        Ok(None) => addr,
+                        Err(e) => {
+                            tracing::error!(%e, "Failed to read PROXY protocol header from {}", addr);
+                            return;
                         }
-                    });
-                }
-                Ok((stream, addr)) = websocket_listener.accept() => {
-                    tokio::spawn(async move {
-                        tracing::info!("New connection from {}", addr);
+                    }
+                } else {
+                    addr
+                };
 
-                        if let Err(e) = connection_loop(StreamType::WebSocket, addr, stream, pg, tx_broker, tx_logger).await {
-                            tracing::error!(%e, "Failed to establish WebSocket stream");
-                        }
-                    });
+                tracing::info!("New connection from {} ({})", addr, stream_type);
+
+                let name = stream_type.to_string();
+                if let Err(e) = connection_loop(stream_type, addr, stream, shared).await {
+                    tracing::error!(%e, "Failed to establish {} stream", name);
                 }
-            }
+            });
         }
     }
 }
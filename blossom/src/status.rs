@@ -0,0 +1,144 @@
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use parking_lot::RwLock;
+
+use crate::config::Config;
+
+pub type StatusHandle = Arc<RwLock<ServerStatus>>;
+
+// MSSP subnegotiation markers.
+const MSSP_VAR: u8 = 1;
+const MSSP_VAL: u8 = 2;
+
+/// A snapshot of the server stats reported through MSSP (MUD Server Status
+/// Protocol). The game loop keeps this up to date, which lets connections
+/// answer MUD listing crawlers without ever touching the world.
+#[derive(Debug, Clone)]
+pub struct ServerStatus {
+    pub name: String,
+    pub players: usize,
+    // Unix timestamp of when the server started, which is how MSSP expects
+    // uptime to be reported.
+    pub started: u64,
+    pub ports: Vec<u16>,
+    pub tls_ports: Vec<u16>,
+}
+
+impl ServerStatus {
+    pub fn new(config: &Config) -> Self {
+        let mut tls_ports = Vec::new();
+        if config.tls.enabled {
+            tls_ports.push(config.tls.telnet_port);
+        }
+
+        Self {
+            name: config.game.name.clone(),
+            players: 0,
+            started: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            ports: vec![config.game.telnet_port],
+            tls_ports,
+        }
+    }
+
+    pub fn handle(self) -> StatusHandle {
+        Arc::new(RwLock::new(self))
+    }
+
+    /// Returns the MSSP variables and their values. Variables can have more
+    /// than one value, such as when listening on multiple ports.
+    pub fn variables(&self) -> Vec<(&'static str, Vec<String>)> {
+        let join = |ports: &[u16]| ports.iter().map(u16::to_string).collect();
+
+        vec![
+            ("NAME", vec![self.name.clone()]),
+            ("PLAYERS", vec![self.players.to_string()]),
+            ("UPTIME", vec![self.started.to_string()]),
+            (
+                "CODEBASE",
+                vec![format!("Blossom {}", env!("CARGO_PKG_VERSION"))],
+            ),
+            ("PORT", join(&self.ports)),
+            (
+                "SSL",
+                if self.tls_ports.is_empty() {
+                    vec!["0".to_string()]
+                } else {
+                    join(&self.tls_ports)
+                },
+            ),
+            ("ANSI", vec!["1".to_string()]),
+            ("UTF-8", vec!["1".to_string()]),
+            ("GMCP", vec!["1".to_string()]),
+            ("MCCP", vec!["1".to_string()]),
+        ]
+    }
+
+    /// Encodes the variables as the payload of an MSSP subnegotiation.
+    pub fn to_subnegotiation(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+
+        for (name, values) in self.variables() {
+            buf.put_u8(MSSP_VAR);
+            buf.put_slice(name.as_bytes());
+
+            for value in values {
+                buf.put_u8(MSSP_VAL);
+                buf.put_slice(value.as_bytes());
+            }
+        }
+
+        buf.freeze()
+    }
+
+    /// Formats the variables as a plain-text MSSP reply, which is sent to
+    /// crawlers that send `MSSP-REQUEST` instead of negotiating MSSP.
+    pub fn to_plain_text(&self) -> String {
+        let mut output = String::from("\r\nMSSP-REPLY-START\r\n");
+
+        for (name, values) in self.variables() {
+            output.push_str(name);
+
+            for value in values {
+                output.push('\t');
+                output.push_str(&value);
+            }
+
+            output.push_str("\r\n");
+        }
+
+        output.push_str("MSSP-REPLY-END\r\n");
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_subnegotiation() {
+        let status = ServerStatus::new(&Config::default());
+        let payload = status.to_subnegotiation();
+
+        assert!(payload.starts_with(b"\x01NAME\x02Blossom\x01PLAYERS\x020\x01UPTIME\x02"));
+    }
+
+    #[test]
+    fn plain_text_reply() {
+        let mut status = ServerStatus::new(&Config::default());
+        status.ports.push(5080);
+
+        let reply = status.to_plain_text();
+
+        assert!(reply.starts_with("\r\nMSSP-REPLY-START\r\nNAME\tBlossom\r\n"));
+        assert!(reply.contains("\r\nPORT\t5000\t5080\r\n"));
+        assert!(reply.ends_with("MSSP-REPLY-END\r\n"));
+    }
+}
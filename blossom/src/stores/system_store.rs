@@ -1,7 +1,7 @@
 use iridescent::Styled;
 
 use crate::{
-    system::{SystemHandle, SystemReadOnlyHandle, SystemStatus, WatchStatus},
+    system::{SystemHandle, SystemReadOnlyHandle, SystemSchedule, SystemStatus, WatchStatus},
     systems::{execution_timer::ExecutionTimer, watcher::SystemWatcher},
     theme,
 };
@@ -11,6 +11,9 @@ pub struct SystemStore {
     pub readonly: Vec<SystemReadOnlyHandle>,
     pub execution_timer: ExecutionTimer,
     pub watcher: SystemWatcher,
+    // A system which takes more than this percentage of a tick is warned
+    // about. 0 turns the warning off.
+    pub slow_system_percent: u32,
 }
 
 impl SystemStore {
@@ -20,6 +23,7 @@ impl SystemStore {
             readonly: Vec::new(),
             execution_timer: ExecutionTimer::new(),
             watcher: SystemWatcher::new(),
+            slow_system_percent: 25,
         }
     }
 
@@ -44,6 +48,119 @@ impl SystemStore {
 
         result
     }
+
+    /// Describes how long each system has taken to run, in the order they run
+    /// in, for admins.
+    pub fn timings(&self) -> String {
+        self.readonly
+            .iter()
+            .map(|s| format!("\n    {s}: {}", s.timings))
+            .chain(
+                self.write
+                    .iter()
+                    .map(|s| format!("\n    {s}: {}", s.timings)),
+            )
+            .collect()
+    }
+
+    /// Puts the systems of each kind in the order they run in. This happens
+    /// whenever a system is added, so the game loop can simply run them in
+    /// turn.
+    pub fn sort(&mut self) {
+        self.write = ordered(std::mem::take(&mut self.write), |s| (s.name, &s.schedule));
+        self.readonly = ordered(std::mem::take(&mut self.readonly), |s| {
+            (s.name, &s.schedule)
+        });
+    }
+}
+
+/// Reorders systems into the order they run in. If their constraints can't all
+/// be met, they are only ordered by stage.
+fn ordered<T>(systems: Vec<T>, key: impl Fn(&T) -> (&'static str, &SystemSchedule)) -> Vec<T> {
+    let schedules = systems.iter().map(key).collect::<Vec<_>>();
+
+    let order = run_order(&schedules).unwrap_or_else(|names| {
+        tracing::error!(
+            "Systems can't be ordered as they ask to be: {}. Ordering them by stage only.",
+            names.join(", ")
+        );
+
+        let mut order = (0..schedules.len()).collect::<Vec<_>>();
+        order.sort_by_key(|i| schedules.get(*i).map(|(_, schedule)| schedule.stage));
+        order
+    });
+
+    let mut systems = systems.into_iter().map(Some).collect::<Vec<_>>();
+    order
+        .into_iter()
+        .filter_map(|i| systems.get_mut(i).and_then(Option::take))
+        .collect()
+}
+
+/// Works out the order systems run in, as positions in `systems`. Systems run
+/// by stage, then by their `before` and `after` constraints, and otherwise in
+/// the order they are given. Constraints naming systems which aren't in the
+/// list are ignored.
+///
+/// Returns the names of the systems which couldn't be ordered if the
+/// constraints contradict each other.
+fn run_order(
+    systems: &[(&'static str, &SystemSchedule)],
+) -> std::result::Result<Vec<usize>, Vec<&'static str>> {
+    // Each system's list of systems which have to run after it.
+    let mut edges = vec![Vec::new(); systems.len()];
+
+    for (i, (a_name, a)) in systems.iter().enumerate() {
+        for (j, (b_name, b)) in systems.iter().enumerate() {
+            let a_first =
+                a.stage < b.stage || a.before.contains(b_name) || b.after.contains(a_name);
+
+            if i != j && a_first {
+                if let Some(edges) = edges.get_mut(i) {
+                    edges.push(j);
+                }
+            }
+        }
+    }
+
+    let mut waiting_on = vec![0; systems.len()];
+    for j in edges.iter().flatten() {
+        if let Some(count) = waiting_on.get_mut(*j) {
+            *count += 1;
+        }
+    }
+
+    let mut order = Vec::with_capacity(systems.len());
+    let mut done = vec![false; systems.len()];
+
+    // Always take the earliest system which isn't waiting on any others, so
+    // unconstrained systems keep the order they were given in.
+    while let Some(next) = (0..systems.len())
+        .find(|i| !done.get(*i).copied().unwrap_or(true) && waiting_on.get(*i) == Some(&0))
+    {
+        if let Some(done) = done.get_mut(next) {
+            *done = true;
+        }
+
+        for j in edges.get(next).into_iter().flatten() {
+            if let Some(count) = waiting_on.get_mut(*j) {
+                *count -= 1;
+            }
+        }
+
+        order.push(next);
+    }
+
+    if order.len() < systems.len() {
+        return Err(systems
+            .iter()
+            .zip(&done)
+            .filter(|(_, done)| !**done)
+            .map(|((name, _), _)| *name)
+            .collect());
+    }
+
+    Ok(order)
 }
 
 impl Default for SystemStore {
@@ -67,8 +184,21 @@ impl std::fmt::Display for SystemStore {
 
         let system_set = s1.iter().chain(s2.iter()).copied().collect::<Vec<_>>();
 
+        // Readonly systems run first, and each kind runs in the order it is
+        // stored in.
+        let schedule = self
+            .readonly
+            .iter()
+            .map(|s| format!("\n    {s}: {}", s.schedule))
+            .chain(
+                self.write
+                    .iter()
+                    .map(|s| format!("\n    {s}: {}", s.schedule)),
+            )
+            .collect::<String>();
+
         let text = format!(
-            "{} ({}, {} readonly)\n  Running: [{}]\n  Paused: [{}]\n  Stopped: [{}]",
+            "{} ({}, {} readonly)\n  Running: [{}]\n  Paused: [{}]\n  Stopped: [{}]\n  Schedule:{}\n  Timings:{}",
             system_set.len().to_string().foreground(theme::GREEN).bold(),
             self.write.len().to_string().foreground(theme::GREEN).bold(),
             self.readonly
@@ -94,6 +224,8 @@ impl std::fmt::Display for SystemStore {
                 .map(|s| format!("{}", s.0.to_string().foreground(theme::RED).bold()))
                 .collect::<Vec<_>>()
                 .join(", "),
+            schedule,
+            self.timings(),
         );
 
         write!(f, "{text}",)
@@ -104,7 +236,8 @@ impl std::fmt::Display for SystemStore {
 mod tests {
     use super::*;
     use crate::{
-        system::{System, WatchStatus},
+        system::{Stage, System, WatchStatus},
+        timings::Timings,
         world::World,
     };
 
@@ -123,6 +256,8 @@ mod tests {
             name: "test",
             status: SystemStatus::Running,
             watch: WatchStatus::Automatic,
+            schedule: SystemSchedule::every_tick(),
+            timings: Timings::new(),
         });
 
         assert!(system_store.set_status("test", SystemStatus::Running));
@@ -130,4 +265,46 @@ mod tests {
         assert!(system_store.set_status("test", SystemStatus::Stopped));
         assert!(!system_store.set_status("non_existent_test", SystemStatus::Paused));
     }
+
+    #[test]
+    fn run_order_follows_stages_and_constraints() {
+        let save = SystemSchedule::every_seconds(300).stage(Stage::Last);
+        let regen = SystemSchedule::every_tick().after("combat");
+        let combat = SystemSchedule::every_tick();
+        let input = SystemSchedule::every_tick().stage(Stage::First);
+        let weather = SystemSchedule::every_tick().before("regen");
+
+        let systems = [
+            ("save", &save),
+            ("regen", &regen),
+            ("combat", &combat),
+            ("input", &input),
+            ("weather", &weather),
+        ];
+
+        assert_eq!(run_order(&systems), Ok(vec![3, 2, 4, 1, 0]));
+    }
+
+    #[test]
+    fn run_order_rejects_contradictions() {
+        let a = SystemSchedule::every_tick().before("b");
+        let b = SystemSchedule::every_tick().before("a");
+        let c = SystemSchedule::every_tick();
+
+        assert_eq!(
+            run_order(&[("a", &a), ("b", &b), ("c", &c)]),
+            Err(vec!["a", "b"])
+        );
+
+        // A system can't be ordered against the stages.
+        let early = SystemSchedule::every_tick()
+            .stage(Stage::Last)
+            .before("late");
+        let late = SystemSchedule::every_tick();
+
+        assert_eq!(
+            run_order(&[("early", &early), ("late", &late)]),
+            Err(vec!["early", "late"])
+        );
+    }
 }
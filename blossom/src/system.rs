@@ -15,9 +15,18 @@
 /// simply as a marker with no data -- but if you wish to store your own state,
 /// this struct is where you would do so.
 ///
+/// Each system is also added with a `SystemSchedule`, which says how often it
+/// runs and where it runs within a tick, relative to other systems. Systems
+/// which only need to run every so often should use this rather than keeping
+/// track of when they last ran themselves.
+///
+/// A system which panics is stopped, and the admins who are online are told
+/// about it, so the rest of the game keeps running. It can be started again
+/// with `@systems start <name>`.
+///
 /// Systems are still a work-in-progress, so the API may change as I try to
 /// simplify usage while increasing the extendability.
-use crate::world::World;
+use crate::{timings::Timings, world::World};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SystemStatus {
@@ -32,6 +41,168 @@ pub enum WatchStatus {
     Automatic,
 }
 
+/// How often a system runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Run {
+    EveryTick,
+    Ticks(u64),
+    Seconds(u64),
+}
+
+/// The stages of a tick that systems run in, in order. Within a stage, systems
+/// run in the order they were added, unless told to run before or after
+/// another system.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    First,
+    #[default]
+    Update,
+    Last,
+}
+
+impl std::fmt::Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stage::First => write!(f, "first"),
+            Stage::Update => write!(f, "update"),
+            Stage::Last => write!(f, "last"),
+        }
+    }
+}
+
+/// When a system runs, and where it runs within a tick.
+///
+/// ```ignore
+/// world.add_system(
+///     "global_save",
+///     GlobalSave,
+///     SystemSchedule::every_seconds(300).stage(Stage::Last),
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemSchedule {
+    pub run: Run,
+    // Whether the system only runs while there are players in the world.
+    pub players_online: bool,
+    pub stage: Stage,
+    // The names of systems this one has to run before or after. Systems can
+    // only be ordered against systems of the same kind, as readonly systems
+    // always run first.
+    pub before: Vec<&'static str>,
+    pub after: Vec<&'static str>,
+    // The tick or second the system next runs on, for systems which don't run
+    // every tick.
+    next_run: u64,
+}
+
+impl SystemSchedule {
+    /// Runs every tick.
+    pub fn every_tick() -> Self {
+        Self {
+            run: Run::EveryTick,
+            players_online: false,
+            stage: Stage::default(),
+            before: Vec::new(),
+            after: Vec::new(),
+            next_run: 0,
+        }
+    }
+
+    /// Runs every `ticks` ticks, starting `ticks` ticks into the game.
+    pub fn every_ticks(ticks: u64) -> Self {
+        let ticks = ticks.max(1);
+
+        Self {
+            run: Run::Ticks(ticks),
+            next_run: ticks,
+            ..Self::every_tick()
+        }
+    }
+
+    /// Runs every `seconds` seconds, starting `seconds` seconds into the game.
+    pub fn every_seconds(seconds: u64) -> Self {
+        let seconds = seconds.max(1);
+
+        Self {
+            run: Run::Seconds(seconds),
+            next_run: seconds,
+            ..Self::every_tick()
+        }
+    }
+
+    /// Skips the system while there are no players in the world.
+    pub fn when_players_online(mut self) -> Self {
+        self.players_online = true;
+        self
+    }
+
+    pub fn stage(mut self, stage: Stage) -> Self {
+        self.stage = stage;
+        self
+    }
+
+    pub fn before(mut self, system: &'static str) -> Self {
+        self.before.push(system);
+        self
+    }
+
+    pub fn after(mut self, system: &'static str) -> Self {
+        self.after.push(system);
+        self
+    }
+
+    /// Whether the system should run this tick. If it should, the next run is
+    /// scheduled from now.
+    pub fn is_due(&mut self, tick: u64, seconds: u64, players_online: bool) -> bool {
+        if self.players_online && !players_online {
+            return false;
+        }
+
+        let (now, interval) = match self.run {
+            Run::EveryTick => return true,
+            Run::Ticks(ticks) => (tick, ticks),
+            Run::Seconds(seconds_between) => (seconds, seconds_between),
+        };
+
+        if now < self.next_run {
+            return false;
+        }
+
+        self.next_run = now + interval;
+
+        true
+    }
+}
+
+impl Default for SystemSchedule {
+    fn default() -> Self {
+        Self::every_tick()
+    }
+}
+
+impl std::fmt::Display for SystemSchedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = vec![match self.run {
+            Run::EveryTick => "every tick".to_string(),
+            Run::Ticks(ticks) => format!("every {ticks} ticks"),
+            Run::Seconds(seconds) => format!("every {seconds}s"),
+        }];
+
+        if self.players_online {
+            parts.push("when players are online".to_string());
+        }
+
+        if self.stage != Stage::Update {
+            parts.push(format!("{} stage", self.stage));
+        }
+
+        parts.extend(self.before.iter().map(|name| format!("before {name}")));
+        parts.extend(self.after.iter().map(|name| format!("after {name}")));
+
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
 pub trait System: Send + Sync {
     fn update(&mut self, world: &mut World);
 }
@@ -41,17 +212,21 @@ pub struct SystemHandle {
     pub status: SystemStatus,
     pub watch: WatchStatus,
     pub name: &'static str,
+    pub schedule: SystemSchedule,
+    pub timings: Timings,
     pub inner: Box<dyn System>,
 }
 
 /// Represents a system that has exclusive, mutable access to the world and its
 /// parent struct.
 impl SystemHandle {
-    pub fn new(name: &'static str, inner: Box<dyn System>) -> Self {
+    pub fn new(name: &'static str, inner: Box<dyn System>, schedule: SystemSchedule) -> Self {
         Self {
             status: SystemStatus::Running,
             watch: WatchStatus::Automatic,
             name,
+            schedule,
+            timings: Timings::new(),
             inner,
         }
     }
@@ -75,15 +250,23 @@ pub struct SystemReadOnlyHandle {
     pub status: SystemStatus,
     pub watch: WatchStatus,
     pub name: &'static str,
+    pub schedule: SystemSchedule,
+    pub timings: Timings,
     pub inner: Box<dyn SystemReadOnly>,
 }
 
 impl SystemReadOnlyHandle {
-    pub fn new(name: &'static str, inner: Box<dyn SystemReadOnly>) -> Self {
+    pub fn new(
+        name: &'static str,
+        inner: Box<dyn SystemReadOnly>,
+        schedule: SystemSchedule,
+    ) -> Self {
         Self {
             status: SystemStatus::Running,
             watch: WatchStatus::Automatic,
             name,
+            schedule,
+            timings: Timings::new(),
             inner,
         }
     }
@@ -94,3 +277,33 @@ impl std::fmt::Display for SystemReadOnlyHandle {
         write!(f, "{} (readonly)", self.name)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_on_its_interval() {
+        let mut schedule = SystemSchedule::every_seconds(5);
+
+        assert!(!schedule.is_due(1, 0, true));
+        assert!(schedule.is_due(100, 5, true));
+        assert!(!schedule.is_due(101, 5, true));
+        assert!(!schedule.is_due(180, 9, true));
+        assert!(schedule.is_due(200, 10, true));
+    }
+
+    #[test]
+    fn waits_for_players() {
+        let mut schedule = SystemSchedule::every_ticks(2).when_players_online();
+
+        assert!(!schedule.is_due(2, 0, false));
+        assert!(schedule.is_due(3, 0, true));
+        assert!(!schedule.is_due(4, 0, true));
+        assert!(schedule.is_due(5, 0, true));
+        assert_eq!(
+            schedule.to_string(),
+            "every 2 ticks, when players are online"
+        );
+    }
+}
@@ -1,4 +1,6 @@
-use std::time::Instant;
+use std::time::{Duration, Instant};
+
+use crate::timings::human;
 
 /// Internal, core system for tracking the execution time of game ticks. This
 /// means the time it takes for all of the functions inside the game loop to
@@ -6,11 +8,12 @@ use std::time::Instant;
 /// overall game performance -- you can think of it as your server frame time,
 /// even though iterations are hard-capped by the `tick_rate` variable.
 ///
-/// If somehow your tick execution time exceeded your tick interval (sleep
-/// duration), the game loop would slowly drift off a consistent time, and could
-/// affect other systems signifigantly. In practice, though, this would be
-/// almost impossible to do. The default 50ms per tick leaves an massive amount
-/// of room, even for ineffecient systems or high command processing loads.
+/// Ticks are scheduled against fixed deadlines, so the game loop doesn't drift
+/// when a tick takes a while. A tick which runs past its deadline is an
+/// overrun: the loop catches up by running the next ticks right away, or skips
+/// them if it fell too far behind. Overruns are counted here, as a steady
+/// stream of them means the tick rate is higher than the server can keep up
+/// with.
 ///
 /// Note: This is a UNIQUE system. It does NOT operate on the system queue, and
 /// is baked into the game loop. It will not show up under systems debug output,
@@ -22,6 +25,10 @@ pub struct ExecutionTimer {
     // Tracks iterations of the game loop. Needed to calculate the index for the
     // next time.
     count: u8,
+    // How many ticks ran past their deadline.
+    pub overruns: u64,
+    // How many ticks were skipped because the loop fell too far behind.
+    pub skipped: u64,
 }
 
 impl ExecutionTimer {
@@ -29,6 +36,8 @@ impl ExecutionTimer {
         Self {
             times: [0; 100],
             count: 0,
+            overruns: 0,
+            skipped: 0,
         }
     }
 
@@ -52,15 +61,9 @@ impl ExecutionTimer {
     pub fn average(&self) -> String {
         let time = self.average_tick_execution_time();
 
-        if time < 1000 {
-            format!("{time}ns",)
-        } else if time < 1_000_000 {
-            format!("{}µs", time / 1000)
-        } else if time < 1_000_000_000 {
-            format!("{}ms", time / 1_000_000)
-        } else {
-            format!("{}s", time / 1_000_000_000)
-        }
+        human(Duration::from_nanos(
+            u64::try_from(time).unwrap_or(u64::MAX),
+        ))
     }
 
     pub fn update(&mut self, start: Instant) {
@@ -73,6 +76,23 @@ impl ExecutionTimer {
             tracing::debug!("Average Execution Time: {}", self.average());
         }
     }
+
+    /// Records a tick which ran `late` past its deadline, and how many ticks
+    /// were skipped because of it.
+    pub fn overrun(&mut self, late: Duration, skipped: u64) {
+        self.overruns += 1;
+        self.skipped += skipped;
+
+        if skipped > 0 {
+            tracing::warn!(
+                "Game loop fell {:?} behind; skipped {} ticks",
+                late,
+                skipped
+            );
+        } else {
+            tracing::debug!("Tick overran its deadline by {:?}", late);
+        }
+    }
 }
 
 impl Default for ExecutionTimer {
@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+
+use crate::{
+    gmcp::{Package, RoomInfo, Status, Vitals},
+    player::PlayerId,
+    system::System,
+    world::World,
+};
+
+/// The last set of packages sent to a player.
+#[derive(Debug, Default)]
+struct Sent {
+    vitals: Option<Vitals>,
+    status: Option<Status>,
+    room: Option<RoomInfo>,
+}
+
+/// Internal, core system that keeps GMCP clients in sync with the world. Each
+/// tick it compares the `Char.Vitals`, `Char.Status` and `Room.Info` packages
+/// of every player against what was last sent to them, and only sends the
+/// packages which have changed.
+///
+/// This means commands and systems never have to remember to send these
+/// packages themselves; changing the player is enough.
+#[derive(Debug, Default)]
+pub struct GmcpWatcher {
+    sent: HashMap<PlayerId, Sent>,
+}
+
+impl GmcpWatcher {
+    pub fn new() -> Self {
+        Self {
+            sent: HashMap::new(),
+        }
+    }
+}
+
+impl System for GmcpWatcher {
+    fn update(&mut self, world: &mut World) {
+        let players = world.players.read();
+        let rooms = world.rooms.read();
+
+        // Forget about anyone who has left or gone link-dead, so they get a
+        // full update if they come back.
+        self.sent
+            .retain(|id, _| players.get(id).is_some_and(|p| p.linkdead.is_none()));
+
+        for player in players.iter().filter(|p| p.linkdead.is_none()) {
+            let sent = self.sent.entry(player.id).or_default();
+
+            let vitals = Vitals::from(player);
+            if sent.vitals.as_ref() != Some(&vitals) {
+                world.send_gmcp(player.id, Package::CharVitals(vitals.clone()));
+                sent.vitals = Some(vitals);
+            }
+
+            let status = Status::from(player);
+            if sent.status.as_ref() != Some(&status) {
+                world.send_gmcp(player.id, Package::CharStatus(status.clone()));
+                sent.status = Some(status);
+            }
+
+            if let Some(room) = rooms.get(&player.position) {
+                let info = RoomInfo::new(room, &rooms);
+                if sent.room.as_ref() != Some(&info) {
+                    world.send_gmcp(player.id, Package::RoomInfo(info.clone()));
+                    sent.room = Some(info);
+                }
+            }
+        }
+    }
+}
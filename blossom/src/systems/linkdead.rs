@@ -0,0 +1,31 @@
+use crate::{system::System, world::World};
+
+/// Internal, core system that removes link-dead players from the world once
+/// they have been gone for longer than the configured timeout.
+pub struct LinkDeadReaper {
+    pub timeout: u64,
+}
+
+impl LinkDeadReaper {
+    pub fn new(timeout: u64) -> Self {
+        Self { timeout }
+    }
+}
+
+impl System for LinkDeadReaper {
+    fn update(&mut self, world: &mut World) {
+        let now = world.timer.seconds;
+        let expired = world
+            .players
+            .read()
+            .iter()
+            .filter(|p| p.linkdead.is_some_and(|since| since + self.timeout <= now))
+            .map(|p| p.id)
+            .collect::<Vec<_>>();
+
+        for id in expired {
+            tracing::info!("Removing link-dead player {}", id);
+            world.remove_player(id);
+        }
+    }
+}
@@ -1,4 +1,6 @@
 pub mod execution_timer;
 pub mod global_save;
+pub mod gmcp;
+pub mod linkdead;
 pub mod spawner;
 pub mod watcher;
@@ -0,0 +1,106 @@
+use sqlx::PgPool;
+use time::OffsetDateTime;
+
+use crate::{error::Result, player::PlayerId};
+
+/// A private message which was sent while its recipient was offline.
+#[derive(Clone, Debug)]
+pub struct Tell {
+    pub sender: String,
+    pub message: String,
+    pub sent_on: OffsetDateTime,
+}
+
+impl std::fmt::Display for Tell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let elapsed = OffsetDateTime::now_utc() - self.sent_on;
+        let age = if elapsed.whole_days() > 0 {
+            format!("{}d", elapsed.whole_days())
+        } else if elapsed.whole_hours() > 0 {
+            format!("{}h", elapsed.whole_hours())
+        } else {
+            format!("{}m", elapsed.whole_minutes().max(1))
+        };
+
+        write!(f, "{} ({age} ago)", received(&self.sender, &self.message))
+    }
+}
+
+/// Formats a tell as the recipient sees it.
+pub fn received(sender: &str, message: &str) -> String {
+    format!("{sender} tells you, \"{message}\"")
+}
+
+/// Formats a tell as the sender sees it.
+pub fn sent(recipient: &str, message: &str) -> String {
+    format!("You tell {recipient}, \"{message}\"")
+}
+
+/// Stores a tell for a player who is offline. Returns the recipient's name, or
+/// `None` if there is no player by that name.
+pub async fn store(
+    sender: PlayerId,
+    recipient: &str,
+    message: &str,
+    pg: &PgPool,
+) -> Result<Option<String>> {
+    let record = sqlx::query!(
+        r#"with target as (select id, name from players where name = $2),
+        inserted as (
+            insert into tells (sender, recipient, message)
+            select $1, target.id, $3 from target
+            returning recipient
+        )
+        select target.name as "name!" from target join inserted on inserted.recipient = target.id"#,
+        sender,
+        recipient,
+        message,
+    )
+    .fetch_optional(pg)
+    .await?;
+
+    Ok(record.map(|record| record.name))
+}
+
+/// Takes every tell waiting for a player, oldest first, and marks them as
+/// delivered.
+pub async fn take_pending(recipient: PlayerId, pg: &PgPool) -> Result<Vec<Tell>> {
+    let mut tells = sqlx::query_as!(
+        Tell,
+        r#"update tells t
+        set delivered_on = now()
+        from players p
+        where p.id = t.sender
+        and t.recipient = $1
+        and t.delivered_on is null
+        returning p.name as "sender!", t.message as "message!", t.created_on as "sent_on!""#,
+        recipient,
+    )
+    .fetch_all(pg)
+    .await?;
+
+    tells.sort_by_key(|tell| tell.sent_on);
+
+    Ok(tells)
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn offline_tell_shows_its_age() {
+        let tell = Tell {
+            sender: "Alice".to_string(),
+            message: "See you tomorrow!".to_string(),
+            sent_on: OffsetDateTime::now_utc() - Duration::hours(3),
+        };
+
+        assert_eq!(
+            tell.to_string(),
+            "Alice tells you, \"See you tomorrow!\" (3h ago)"
+        );
+    }
+}
@@ -0,0 +1,179 @@
+/// The amount of color a client is able to display. Ordered from least to most
+/// capable, so support levels can be compared directly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorSupport {
+    None,
+    Ansi16,
+    Ansi256,
+    TrueColor,
+}
+
+/// Represents what a client terminal is capable of, as reported through TTYPE
+/// and MTTS (MUD Terminal Type Standard). Output is rendered to match these
+/// capabilities before being sent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    pub color: ColorSupport,
+    pub utf8: bool,
+    // Whether the client is using a screen reader. For now this only turns
+    // color off; output is otherwise the same as for any other client.
+    pub screen_reader: bool,
+}
+
+// MTTS bit flags. See https://tintin.mudhalla.net/protocols/mtts/
+const MTTS_ANSI: u32 = 1;
+const MTTS_UTF8: u32 = 4;
+const MTTS_256_COLORS: u32 = 8;
+const MTTS_SCREEN_READER: u32 = 64;
+const MTTS_TRUECOLOR: u32 = 256;
+
+impl Capabilities {
+    /// The capabilities we assume for a Telnet client until it tells us
+    /// otherwise. Basic colors are the safest bet for an unknown terminal, so
+    /// clients which never answer TTYPE don't get escapes they can't display.
+    pub fn new() -> Self {
+        Self {
+            color: ColorSupport::Ansi16,
+            utf8: true,
+            screen_reader: false,
+        }
+    }
+
+    /// The capabilities of WebSocket clients. These never answer TTYPE, and
+    /// render colors themselves, so they get everything.
+    pub fn web() -> Self {
+        Self {
+            color: ColorSupport::TrueColor,
+            utf8: true,
+            screen_reader: false,
+        }
+    }
+
+    /// The capabilities we fall back to when a client refuses TTYPE, which
+    /// also rules out it telling us it supports UTF-8.
+    pub fn basic() -> Self {
+        Self {
+            color: ColorSupport::Ansi16,
+            utf8: false,
+            screen_reader: false,
+        }
+    }
+
+    /// Parses an MTTS bitvector, which clients send as their third TTYPE
+    /// response in the form `MTTS <bits>`.
+    pub fn from_mtts(bits: u32) -> Self {
+        let screen_reader = bits & MTTS_SCREEN_READER != 0;
+
+        // Screen readers read escape sequences out loud, so they never get
+        // any color regardless of what else the client claims.
+        let color = if screen_reader {
+            ColorSupport::None
+        } else if bits & MTTS_TRUECOLOR != 0 {
+            ColorSupport::TrueColor
+        } else if bits & MTTS_256_COLORS != 0 {
+            ColorSupport::Ansi256
+        } else if bits & MTTS_ANSI != 0 {
+            ColorSupport::Ansi16
+        } else {
+            ColorSupport::None
+        };
+
+        Self {
+            color,
+            utf8: bits & MTTS_UTF8 != 0,
+            screen_reader,
+        }
+    }
+
+    /// Guesses the color support of a terminal from its TTYPE name (eg.
+    /// `XTERM-256COLOR`). Returns `None` for names we don't recognize, such as
+    /// MUD client names, which are sent before the actual terminal type.
+    pub fn color_from_terminal_type(name: &str) -> Option<ColorSupport> {
+        let name = name.to_uppercase();
+
+        if name.contains("TRUECOLOR") || name.contains("24BIT") || name.ends_with("-DIRECT") {
+            Some(ColorSupport::TrueColor)
+        } else if name.contains("256COLOR") {
+            Some(ColorSupport::Ansi256)
+        } else if name == "DUMB" {
+            Some(ColorSupport::None)
+        } else if ["ANSI", "VT100", "XTERM", "LINUX", "SCREEN", "TMUX", "RXVT"]
+            .iter()
+            .any(|t| name.starts_with(t))
+        {
+            Some(ColorSupport::Ansi16)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Tracks the TTYPE request cycle. Clients answer each request with the next
+/// name in their list (client name, terminal type, then MTTS), and repeat the
+/// last answer once they have run out.
+#[derive(Debug, Default)]
+pub struct TerminalTypeCycle {
+    pub requests: u8,
+    pub last: Option<String>,
+}
+
+impl TerminalTypeCycle {
+    // Per MTTS, the third response is the bitvector, so we never need more.
+    const MAX_REQUESTS: u8 = 3;
+
+    /// Records a response and returns whether another request should be made.
+    pub fn record(&mut self, name: &str) -> bool {
+        let repeated = self.last.as_deref() == Some(name);
+        self.last = Some(name.to_string());
+
+        !repeated && !name.starts_with("MTTS ") && self.requests < Self::MAX_REQUESTS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_terminals_get_basic_colors() {
+        assert_eq!(Capabilities::new().color, ColorSupport::Ansi16);
+        assert_eq!(Capabilities::basic().color, ColorSupport::Ansi16);
+    }
+
+    #[test]
+    fn parse_mtts() {
+        // ANSI, VT100, UTF-8, 256 colors, OSC color palette, truecolor
+        let capabilities = Capabilities::from_mtts(1 + 2 + 4 + 8 + 32 + 256);
+
+        assert_eq!(capabilities.color, ColorSupport::TrueColor);
+        assert!(capabilities.utf8);
+        assert!(!capabilities.screen_reader);
+    }
+
+    #[test]
+    fn screen_reader_disables_color() {
+        let capabilities = Capabilities::from_mtts(1 + 4 + 8 + 64);
+
+        assert_eq!(capabilities.color, ColorSupport::None);
+        assert!(capabilities.screen_reader);
+    }
+
+    #[test]
+    fn terminal_type_names() {
+        assert_eq!(
+            Capabilities::color_from_terminal_type("xterm-256color"),
+            Some(ColorSupport::Ansi256)
+        );
+        assert_eq!(
+            Capabilities::color_from_terminal_type("VT100"),
+            Some(ColorSupport::Ansi16)
+        );
+        assert_eq!(Capabilities::color_from_terminal_type("MUDLET"), None);
+    }
+}
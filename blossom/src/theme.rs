@@ -8,3 +8,19 @@ pub const LIGHT_GREEN: &str = "#83571e";
 pub const TEAL: &str = "#83571e";
 pub const LIGHT_BLUE: &str = "#567c7d";
 pub const GRAY: &str = "#b4b3a0";
+
+/// Looks up a theme color by name, such as "green" or "light_blue".
+pub fn color(name: &str) -> Option<&'static str> {
+    match name.to_lowercase().as_str() {
+        "red" => Some(RED),
+        "blue" => Some(BLUE),
+        "yellow" => Some(YELLOW),
+        "green" => Some(GREEN),
+        "orange" => Some(ORANGE),
+        "light_green" => Some(LIGHT_GREEN),
+        "teal" => Some(TEAL),
+        "light_blue" => Some(LIGHT_BLUE),
+        "gray" | "grey" => Some(GRAY),
+        _ => None,
+    }
+}
@@ -1,4 +1,28 @@
-use std::{fmt::Display, time::Instant};
+use std::{
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+/// The tick rate used when none is configured.
+pub const DEFAULT_TICK_RATE: u64 = 20;
+
+// How many ticks the game loop can fall behind by and still catch up, by
+// running the missed ticks back to back. Past this, the missed ticks are
+// skipped, so a long stall doesn't turn into a burst of ticks.
+const MAX_CATCH_UP: u32 = 5;
+
+/// When the next tick of the game loop should run.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Schedule {
+    // The tick finished early, and the loop should wait this long.
+    Wait(Duration),
+    // The tick ran past its deadline by this much, so the next one runs right
+    // away to catch up.
+    CatchUp(Duration),
+    // The loop fell too far behind to catch up. The next tick runs right away,
+    // and this many ticks are skipped.
+    Skip { late: Duration, ticks: u64 },
+}
 
 /// Used as the internal representation of the game loop time. This is updated
 /// on each 'tick' of the game loop. This does NOT relate to in-game game time,
@@ -9,9 +33,11 @@ pub struct Timer {
     pub start_time: Instant,
     // Reference to the tick_rate defined in the config file.
     pub tick_rate: u64,
-    // The interval the game loop should wait before the next tick: 1000 /
-    // tick_rate.
-    pub interval: u64,
+    // The time between the start of each tick: 1s / tick_rate.
+    pub interval: Duration,
+    // When the next tick is due to start. Deadlines are fixed steps from the
+    // start time, so time spent running a tick doesn't push the next one back.
+    pub deadline: Instant,
     // How many seconds have passed since the game loop started.
     pub seconds: u64,
     // How many ticks have passed since the game loop started.
@@ -22,17 +48,68 @@ pub struct Timer {
 }
 
 impl Timer {
-    pub fn new() -> Self {
+    pub fn new(tick_rate: u64) -> Self {
+        // Ticks are scheduled to the nanosecond, so rates are kept to a range
+        // where each tick still has a sensible amount of time.
+        let tick_rate = tick_rate.clamp(1, 1000);
+        let interval = Duration::from_secs(1) / u32::try_from(tick_rate).unwrap_or(1000);
+        let start_time = Instant::now();
+
         Self {
-            start_time: Instant::now(),
-            tick_rate: 20,
-            interval: 1000 / 20,
+            start_time,
+            tick_rate,
+            interval,
+            deadline: start_time + interval,
             seconds: 0,
             count: 0,
             last_action: 0,
         }
     }
 
+    /// Counts a tick as done, and works out when the next one should run, given
+    /// the time is now `now`.
+    pub fn advance(&mut self, now: Instant) -> Schedule {
+        self.count += 1;
+
+        let schedule = if now <= self.deadline {
+            Schedule::Wait(self.deadline - now)
+        } else {
+            let late = now - self.deadline;
+
+            if late <= self.interval * MAX_CATCH_UP {
+                Schedule::CatchUp(late)
+            } else {
+                // Start counting deadlines again from now, giving up on the
+                // ticks which were missed.
+                let ticks = late.as_nanos() / self.interval.as_nanos();
+                self.deadline = now;
+
+                Schedule::Skip {
+                    late,
+                    ticks: u64::try_from(ticks).unwrap_or(u64::MAX),
+                }
+            }
+        };
+
+        // Seconds follow the clock rather than the tick count, so they stay
+        // right even when ticks are skipped.
+        self.seconds = self
+            .deadline
+            .max(now)
+            .duration_since(self.start_time)
+            .as_secs();
+        self.deadline += self.interval;
+
+        schedule
+    }
+
+    /// How many ticks it takes for `duration` to pass, rounded up.
+    pub fn ticks(&self, duration: Duration) -> u64 {
+        let ticks = duration.as_nanos().div_ceil(self.interval.as_nanos());
+
+        u64::try_from(ticks).unwrap_or(u64::MAX)
+    }
+
     /// Returns the game uptime in human-readable format (HH:MM:SS).
     pub fn uptime(&self) -> String {
         let hours = self.seconds / 3600;
@@ -45,7 +122,7 @@ impl Timer {
 
 impl Default for Timer {
     fn default() -> Self {
-        Self::new()
+        Self::new(DEFAULT_TICK_RATE)
     }
 }
 
@@ -57,7 +134,71 @@ impl Display for Timer {
             self.uptime(),
             self.count,
             self.tick_rate,
-            self.interval
+            self.interval.as_millis()
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uses_tick_rate() {
+        assert_eq!(Timer::new(20).interval, Duration::from_millis(50));
+        assert_eq!(Timer::new(4).interval, Duration::from_millis(250));
+        assert_eq!(Timer::new(0).interval, Duration::from_secs(1));
+        assert_eq!(Timer::new(20).ticks(Duration::from_secs(5)), 100);
+        assert_eq!(Timer::new(20).ticks(Duration::from_millis(120)), 3);
+    }
+
+    #[test]
+    fn waits_for_deadline() {
+        let mut timer = Timer::new(20);
+        let start = timer.start_time;
+
+        // A tick which took 10ms waits out the rest of its 50ms.
+        let now = start + Duration::from_millis(10);
+        assert_eq!(
+            timer.advance(now),
+            Schedule::Wait(Duration::from_millis(40))
+        );
+
+        // The next deadline doesn't move, however long the wait took.
+        let now = start + Duration::from_millis(70);
+        assert_eq!(
+            timer.advance(now),
+            Schedule::Wait(Duration::from_millis(30))
+        );
+    }
+
+    #[test]
+    fn catches_up_then_skips() {
+        let mut timer = Timer::new(20);
+        let start = timer.start_time;
+
+        let now = start + Duration::from_millis(110);
+        assert_eq!(
+            timer.advance(now),
+            Schedule::CatchUp(Duration::from_millis(60))
+        );
+
+        let now = start + Duration::from_millis(3100);
+        assert_eq!(
+            timer.advance(now),
+            Schedule::Skip {
+                late: Duration::from_millis(3000),
+                ticks: 60
+            }
+        );
+        assert_eq!(timer.seconds, 3);
+        assert_eq!(timer.count, 2);
+
+        // Deadlines start again from where the loop got back to.
+        let now = start + Duration::from_millis(3110);
+        assert_eq!(
+            timer.advance(now),
+            Schedule::Wait(Duration::from_millis(40))
+        );
+    }
+}
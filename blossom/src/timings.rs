@@ -0,0 +1,145 @@
+use std::{collections::VecDeque, time::Duration};
+
+// How many of the most recent calls the stats are worked out from.
+const WINDOW: usize = 100;
+
+/// Rolling timing stats for something which runs on the game loop, such as a
+/// system or command. The minimum, maximum and 95th percentile are worked out
+/// from the last `WINDOW` calls, so they reflect how it is doing now rather
+/// than since the server started.
+#[derive(Debug, Default)]
+pub struct Timings {
+    samples: VecDeque<Duration>,
+    // How many times it was called in total.
+    pub calls: u64,
+    // How many calls went over their budget.
+    pub slow: u64,
+    // The second (of server uptime) a slow call was last warned about.
+    warned_at: Option<u64>,
+}
+
+impl Timings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, duration: Duration) {
+        if self.samples.len() >= WINDOW {
+            self.samples.pop_front();
+        }
+
+        self.samples.push_back(duration);
+        self.calls += 1;
+    }
+
+    /// Counts a call which went over its budget. Returns whether it should be
+    /// warned about, which is at most once a second, so something which is
+    /// always slow doesn't flood the log.
+    pub fn over_budget(&mut self, second: u64) -> bool {
+        self.slow += 1;
+
+        if self.warned_at == Some(second) {
+            return false;
+        }
+
+        self.warned_at = Some(second);
+
+        true
+    }
+
+    pub fn min(&self) -> Option<Duration> {
+        self.samples.iter().min().copied()
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        self.samples.iter().max().copied()
+    }
+
+    /// The duration 95% of recent calls finished within.
+    pub fn p95(&self) -> Option<Duration> {
+        let mut samples = self.samples.iter().copied().collect::<Vec<_>>();
+        samples.sort();
+
+        let rank = (samples.len() * 95).div_ceil(100);
+        samples.get(rank.checked_sub(1)?).copied()
+    }
+}
+
+impl std::fmt::Display for Timings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (Some(min), Some(p95), Some(max)) = (self.min(), self.p95(), self.max()) else {
+            return write!(f, "not run yet");
+        };
+
+        write!(
+            f,
+            "{} calls, min {}, p95 {}, max {}",
+            self.calls,
+            human(min),
+            human(p95),
+            human(max)
+        )?;
+
+        if self.slow > 0 {
+            write!(f, ", {} slow", self.slow)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Formats a duration in the largest unit it has a whole number of, eg. `12ms`.
+pub fn human(duration: Duration) -> String {
+    let nanos = duration.as_nanos();
+
+    if nanos < 1000 {
+        format!("{nanos}ns")
+    } else if nanos < 1_000_000 {
+        format!("{}µs", nanos / 1000)
+    } else if nanos < 1_000_000_000 {
+        format!("{}ms", nanos / 1_000_000)
+    } else {
+        format!("{}s", nanos / 1_000_000_000)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rolling_stats() {
+        let mut timings = Timings::new();
+        assert_eq!(timings.to_string(), "not run yet");
+
+        for ms in 1..=100 {
+            timings.record(Duration::from_millis(ms));
+        }
+
+        assert_eq!(timings.min(), Some(Duration::from_millis(1)));
+        assert_eq!(timings.p95(), Some(Duration::from_millis(95)));
+        assert_eq!(timings.max(), Some(Duration::from_millis(100)));
+
+        // The oldest calls fall out of the window, but are still counted.
+        for _ in 0..50 {
+            timings.record(Duration::from_micros(10));
+        }
+
+        assert_eq!(timings.min(), Some(Duration::from_micros(10)));
+        assert_eq!(timings.p95(), Some(Duration::from_millis(95)));
+        assert_eq!(
+            timings.to_string(),
+            "150 calls, min 10µs, p95 95ms, max 100ms"
+        );
+    }
+
+    #[test]
+    fn warns_once_a_second() {
+        let mut timings = Timings::new();
+
+        assert!(timings.over_budget(3));
+        assert!(!timings.over_budget(3));
+        assert!(timings.over_budget(4));
+        assert_eq!(timings.slow, 3);
+    }
+}
@@ -0,0 +1,64 @@
+use std::{fs::File, io::BufReader, sync::Arc};
+
+use tokio_rustls::{
+    rustls::{Certificate, PrivateKey, ServerConfig},
+    TlsAcceptor,
+};
+
+use crate::{
+    config::TlsSettings,
+    error::{Error, ErrorType, Result},
+};
+
+/// Builds a TLS acceptor from the PEM encoded certificate chain and private key
+/// configured in `TlsSettings`. The acceptor is cheap to clone and is shared by
+/// both the Telnet and WebSocket listeners.
+pub fn acceptor(settings: &TlsSettings) -> Result<TlsAcceptor> {
+    let certs = load_certs(&settings.cert_path)?;
+    let key = load_key(&settings.key_path)?;
+
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| Error {
+            kind: ErrorType::Config,
+            message: format!("Invalid TLS certificate or key: {e}"),
+        })?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+fn load_certs(path: &str) -> Result<Vec<Certificate>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let certs = rustls_pemfile::certs(&mut reader)?;
+
+    if certs.is_empty() {
+        return Err(Error {
+            kind: ErrorType::Config,
+            message: format!("No certificates found in {path}"),
+        });
+    }
+
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+/// Loads the first private key in the file. PKCS#8, RSA (PKCS#1), and SEC1 (EC)
+/// keys are all accepted.
+fn load_key(path: &str) -> Result<PrivateKey> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    while let Some(item) = rustls_pemfile::read_one(&mut reader)? {
+        match item {
+            rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::ECKey(key) => return Ok(PrivateKey(key)),
+            _ => continue,
+        }
+    }
+
+    Err(Error {
+        kind: ErrorType::Config,
+        message: format!("No private key found in {path}"),
+    })
+}
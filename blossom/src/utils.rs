@@ -1,5 +1,7 @@
 use std::iter::zip;
 
+use unicode_normalization::UnicodeNormalization;
+
 use crate::constants::{HTTP_METHODS, INVALID_HTTP_VERSIONS};
 
 /// Capitalizes the first letter of a string.
@@ -11,6 +13,13 @@ pub fn capitalize(s: &str) -> String {
     }
 }
 
+/// Puts a player name typed by someone into the form names are stored in, so it
+/// can be looked up. Names are NFC normalized, so an accented letter matches
+/// whether it was typed as one character or as a letter and an accent.
+pub fn normalize_name(name: &str) -> String {
+    capitalize(&name.nfc().collect::<String>().to_lowercase())
+}
+
 /// Guard function for detecting HTTP traffic on the telnet stream. Checks if
 /// the first line of the stream is a valid HTTP method or contains an HTTP
 /// version, and if it is, drops it silently.
@@ -40,6 +49,12 @@ mod tests {
         assert_eq!(capitalize("blossom is cool"), "Blossom is cool");
     }
 
+    #[test]
+    fn normalize_names() {
+        assert_eq!(normalize_name("aLiCe"), "Alice");
+        assert_eq!(normalize_name("zoe\u{308}"), "Zo\u{eb}");
+    }
+
     #[test]
     fn is_http_true() {
         assert!(is_http("GET / HTTP/1.1"));
@@ -7,25 +7,41 @@ use std::{
 
 use flume::{Receiver, Sender};
 use iridescent::Styled;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 
 use crate::{
+    bans::Banned,
+    channel::Channels,
     command::{Command, CommandHandle},
+    config::Config,
     context::Context,
     entity::EntityId,
     error::{Error, ErrorType, Result},
     event::{ClientEvent, Event, GameEvent},
+    gmcp::Package,
+    guard::catch_panic,
+    ignores::Ignored,
+    line_input::Purpose,
+    mail::MailRequest,
     monster::Monster,
+    outbox::Outbox,
     player::{Player, PlayerId},
     prompt::Prompt,
     quickmap::QuickMap,
     region::{Area, Region},
     response::Response,
+    role::Role,
     room::Room,
+    scheduler::Scheduler,
+    status::{ServerStatus, StatusHandle},
     stores::{monster_store::MonsterStore, system_store::SystemStore},
-    system::{System, SystemHandle, SystemReadOnly, SystemReadOnlyHandle, SystemStatus},
+    system::{
+        System, SystemHandle, SystemReadOnly, SystemReadOnlyHandle, SystemSchedule, SystemStatus,
+    },
+    tells::Tell,
     theme,
-    timer::Timer,
+    timer::{Schedule, Timer},
+    timings::{human, Timings},
     vec3::Vec3,
 };
 
@@ -42,12 +58,19 @@ pub struct World {
     pub areas: Vec<Area>,
     pub rooms: Container<Vec3, Room>,
     pub monsters: MonsterStore,
+    pub channels: Channels,
     pub timer: Timer,
     pub systems: SystemStore,
+    // Closures waiting to run after a delay or on an interval.
+    pub scheduler: Scheduler,
     pub command_map: HashMap<String, usize>,
     pub commands: Vec<CommandHandle>,
     pub spawned_entities: u32,
     pub active_entities: u32,
+    // Snapshot of the server stats shared with connections for MSSP.
+    pub status: StatusHandle,
+    // Output waiting to be sent to players at the end of the tick.
+    outbox: Mutex<Outbox>,
 }
 
 impl World {
@@ -62,12 +85,16 @@ impl World {
             areas: Vec::new(),
             rooms: Arc::new(RwLock::new(QuickMap::new())),
             monsters: MonsterStore::new(),
-            timer: Timer::new(),
+            channels: Channels::new(),
+            timer: Timer::default(),
             systems: SystemStore::new(),
+            scheduler: Scheduler::new(),
             command_map: HashMap::new(),
             commands: Vec::new(),
             spawned_entities: 0,
             active_entities: 0,
+            status: ServerStatus::new(&Config::default()).handle(),
+            outbox: Mutex::new(Outbox::new()),
         }
     }
 
@@ -78,21 +105,14 @@ impl World {
             // Process the command queue
             self.process_commands();
 
-            // Run game-specific, readonly systems
-            for system in &self.systems.readonly {
-                if let SystemStatus::Running = system.status {
-                    system.inner.update(self);
-                }
-            }
+            // Run game-specific systems which are due this tick
+            self.run_systems();
 
-            // Run game-specific, writeable systems
-            let mut systems = std::mem::take(&mut self.systems.write);
-            for system in &mut systems {
-                if let SystemStatus::Running = system.status {
-                    system.inner.update(self);
-                }
-            }
-            self.systems.write = systems;
+            // Run tasks which are due this tick
+            self.run_tasks();
+
+            // Send everything players were sent during this tick
+            self.flush_output();
 
             // Internal system for tracking execution time of game ticks.
             self.systems.execution_timer.update(start);
@@ -101,6 +121,110 @@ impl World {
         }
     }
 
+    /// Runs each system which is due this tick. A system which panics is
+    /// stopped, rather than taking the game loop down with it.
+    fn run_systems(&mut self) {
+        let tick = self.timer.count;
+        let seconds = self.timer.seconds;
+        let players_online = !self.players.read().is_empty();
+
+        // Run game-specific, readonly systems
+        let mut systems = std::mem::take(&mut self.systems.readonly);
+        for system in &mut systems {
+            if system.status == SystemStatus::Running
+                && system.schedule.is_due(tick, seconds, players_online)
+            {
+                let start = Instant::now();
+                let result = catch_panic(|| system.inner.update(self));
+                self.record_system(system.name, &mut system.timings, start.elapsed());
+
+                if let Err(message) = result {
+                    system.status = SystemStatus::Stopped;
+                    self.panicked(&format!("System {}", system.name), &message);
+                }
+            }
+        }
+        self.systems.readonly = systems;
+
+        // Run game-specific, writeable systems
+        let mut systems = std::mem::take(&mut self.systems.write);
+        for system in &mut systems {
+            if system.status == SystemStatus::Running
+                && system.schedule.is_due(tick, seconds, players_online)
+            {
+                let start = Instant::now();
+                let result = catch_panic(|| system.inner.update(self));
+                self.record_system(system.name, &mut system.timings, start.elapsed());
+
+                if let Err(message) = result {
+                    system.status = SystemStatus::Stopped;
+                    self.panicked(&format!("System {}", system.name), &message);
+                }
+            }
+        }
+        self.systems.write = systems;
+    }
+
+    /// Lets the admins who are online know a system or scheduled task panicked
+    /// and was stopped. A system stays stopped until an admin starts it again,
+    /// while a task is dropped for good.
+    pub(crate) fn panicked(&self, name: &str, message: &str) {
+        tracing::error!("{} panicked and was stopped: {}", name, message);
+
+        let admins = self
+            .players
+            .read()
+            .iter()
+            .filter(|p| p.account.roles.contains(&Role::Admin))
+            .map(|p| p.id)
+            .collect::<Vec<_>>();
+
+        if admins.is_empty() {
+            return;
+        }
+
+        let msg = format!("{name} panicked and was stopped: {message}")
+            .foreground(theme::RED)
+            .to_string();
+
+        self.send_command(-1, Response::Channel(admins, msg));
+    }
+
+    /// Records how long a system took to run, and warns if it took more than
+    /// its share of the tick.
+    fn record_system(&self, name: &str, timings: &mut Timings, elapsed: Duration) {
+        timings.record(elapsed);
+
+        let percent = self.systems.slow_system_percent;
+        let budget = self.timer.interval * percent / 100;
+
+        if percent > 0 && elapsed > budget && timings.over_budget(self.timer.seconds) {
+            tracing::warn!(
+                "System {} took {}, over {}% of the {} tick",
+                name,
+                human(elapsed),
+                percent,
+                human(self.timer.interval)
+            );
+        }
+    }
+
+    /// Describes how long each command has taken to run, slowest first, for
+    /// admins. Commands which haven't been run are left out.
+    pub fn command_timings(&self) -> String {
+        let mut commands = self
+            .commands
+            .iter()
+            .filter(|c| c.timings.calls > 0)
+            .collect::<Vec<_>>();
+        commands.sort_by_key(|c| std::cmp::Reverse(c.timings.p95()));
+
+        commands
+            .iter()
+            .map(|c| format!("\n    {}: {}", c.inner.name, c.timings))
+            .collect()
+    }
+
     /// Loops through commands received from the broker and processes them. Note
     /// that this is not ONLY for game-specific commands, but all peer-sent
     /// messages that are valid and parsed as an Input struct. This includes
@@ -111,6 +235,16 @@ impl World {
 
             match event {
                 ClientEvent::Connect(mut player, _) => {
+                    // If the player is still in the world, either link-dead or
+                    // playing from another client, the new connection takes
+                    // over the existing player instead.
+                    if self.resume_player(&player) {
+                        self.timer.last_action = Instant::now()
+                            .duration_since(self.timer.start_time)
+                            .as_secs();
+                        continue;
+                    }
+
                     let mut msg = String::new();
 
                     if player.seen {
@@ -122,47 +256,79 @@ impl World {
                         );
                     }
 
+                    if player.unread_mail > 0 {
+                        msg.push_str(&format!(
+                            "You have {} unread mail. Type `mail` to read it.\n",
+                            player.unread_mail
+                        ));
+                    }
+
                     self.send_event(id, GameEvent::Accepted(Response::Client(msg)));
 
                     player._entityid = self.next_id();
 
+                    // Players entering the world for the first time are
+                    // subscribed to the channels which are joined automatically.
+                    if player.channels.is_none() {
+                        player.channels = Some(self.channels.defaults(&player.account));
+                        player.dirty = true;
+                    }
+
                     self.players.write().insert(player);
                     self.timer.last_action = Instant::now()
                         .duration_since(self.timer.start_time)
                         .as_secs();
                 }
-                ClientEvent::Disconnect => {
-                    let binding = self.players.read();
-                    let Some(player) = binding.iter().find(|p| p.id == id) else {
-                        continue;
-                    };
-
-                    // Sends a Save event to the broker which will handle
-                    // the actual database update. We only send this if the
-                    // player is marked for saving; same as global save.
-                    if player.dirty {
-                        self.send_event(id, GameEvent::Save(player.clone()));
-                    }
-
-                    let _ = self.active_entities.saturating_sub(1);
-
-                    // Remove the player from the world
-                    self.players.write().remove(&id);
+                ClientEvent::Disconnect(_) => {
+                    self.remove_player(id);
                     self.timer.last_action = Instant::now()
                         .duration_since(self.timer.start_time)
                         .as_secs();
                 }
+                ClientEvent::LinkDead(_) => {
+                    if let Some(player) = self.players.write().get_mut(&id) {
+                        tracing::info!("{} has gone link-dead", player.name);
+                        player.linkdead = Some(self.timer.seconds);
+                    }
+                }
                 ClientEvent::Ping => self.send_prompt(id),
+                ClientEvent::Tells(tells) => self.deliver_tells(id, tells),
+                ClientEvent::Ignores(ignores) => self.update_ignores(id, ignores),
+                ClientEvent::Banned(banned) => self.kick_banned(&banned),
+                ClientEvent::LineInput(purpose, text) => {
+                    match purpose {
+                        Purpose::Mail { recipient, subject } => self.send_event(
+                            id,
+                            GameEvent::Mail(MailRequest::Send {
+                                recipient,
+                                subject,
+                                body: text,
+                            }),
+                        ),
+                    }
+
+                    self.send_prompt(id);
+                }
                 ClientEvent::Command(tokens) => {
                     let result = match self.command_map.get(&tokens.command) {
                         Some(i) => {
                             let mut commands = std::mem::take(&mut self.commands);
 
                             if let Some(c) = commands.get_mut(*i) {
-                                let result = (c.func)(Context::new(id, tokens, self));
+                                let start = Instant::now();
+                                let result =
+                                    catch_panic(|| (c.func)(Context::new(id, tokens, self)));
+                                c.timings.record(start.elapsed());
 
+                                let name = c.inner.name;
                                 self.commands = commands;
-                                result
+
+                                // A command which panicked is reported like any
+                                // other error, and the game carries on.
+                                result.unwrap_or_else(|message| {
+                                    let message = format!("Command {name} panicked: {message}");
+                                    Err(Error::new(ErrorType::Internal, &message))
+                                })
                             } else {
                                 World::unknown(id)
                             }
@@ -170,8 +336,17 @@ impl World {
                         None => World::unknown(id),
                     };
 
-                    if let Ok(response) = result {
-                        self.send_command(id, response);
+                    match result {
+                        Ok(response) => self.send_command(id, response),
+                        Err(e) => {
+                            tracing::error!(%e, "Failed to run command");
+                            self.send_command(
+                                id,
+                                Response::client_message(
+                                    "Something went wrong; please try again later.",
+                                ),
+                            );
+                        }
                     }
 
                     self.send_prompt(id);
@@ -184,9 +359,126 @@ impl World {
         }
     }
 
-    /// Sends a `GameEvent` to the broker.
+    /// Hands a player which is already in the world over to a new connection,
+    /// keeping their in-game state rather than the copy loaded from the
+    /// database. Returns false if the player isn't in the world.
+    fn resume_player(&mut self, player: &Player) -> bool {
+        let wrap_width = match self.players.write().get_mut(&player.id) {
+            Some(existing) => {
+                existing.linkdead = None;
+                existing._addr = player._addr;
+                existing.wrap_width
+            }
+            None => return false,
+        };
+
+        let msg = format!(
+            "\n{}{}{}\n",
+            "You take control of ".foreground(theme::YELLOW).bold(),
+            player.name.foreground(theme::BLUE).bold(),
+            " again.".foreground(theme::YELLOW).bold(),
+        );
+
+        self.send_event(
+            player.id,
+            GameEvent::WrapWidth(usize::try_from(wrap_width).unwrap_or_default()),
+        );
+        self.send_event(player.id, GameEvent::Accepted(Response::Client(msg)));
+
+        true
+    }
+
+    /// Shows a player the tells they were sent while they were offline. The
+    /// last sender is who `reply` answers.
+    fn deliver_tells(&mut self, id: PlayerId, tells: Vec<Tell>) {
+        let Some(last) = tells.last() else {
+            return;
+        };
+
+        if let Some(player) = self.players.write().get_mut(&id) {
+            player.reply_to = Some(last.sender.clone());
+        }
+
+        let mut lines = vec!["While you were away:".foreground(theme::YELLOW).to_string()];
+        lines.extend(tells.iter().map(ToString::to_string));
+
+        self.send_command(id, Response::client_message(lines.join("\n")));
+    }
+
+    /// Replaces the ignore list of every character online on a player's
+    /// account, as the list belongs to the account.
+    fn update_ignores(&mut self, id: PlayerId, ignores: Vec<Ignored>) {
+        let mut binding = self.players.write();
+        let Some(account) = binding.get(&id).map(|p| p.account.id) else {
+            return;
+        };
+
+        for player in binding.iter_mut().filter(|p| p.account.id == account) {
+            player.ignores = ignores.clone();
+        }
+    }
+
+    /// Kicks everyone a new ban covers who is online. Staff are never kicked,
+    /// as they can't be banned.
+    fn kick_banned(&self, banned: &Banned) {
+        let kicked = self
+            .players
+            .read()
+            .iter()
+            .filter(|p| banned.covers(p) && !p.account.is_moderator())
+            .map(|p| p.id)
+            .collect::<Vec<_>>();
+
+        for id in kicked {
+            self.send_command(
+                id,
+                Response::client_message(format!("\nYou have been banned: {}", banned.reason)),
+            );
+            self.send_command(id, Response::Close);
+        }
+    }
+
+    /// Removes a player from the world, saving them first if they have
+    /// unsaved changes.
+    pub fn remove_player(&mut self, id: PlayerId) {
+        let Some(player) = self.players.read().get(&id).cloned() else {
+            return;
+        };
+
+        // Sends a Save event to the broker which will handle the actual
+        // database update. We only send this if the player is marked for
+        // saving; same as global save.
+        if player.dirty {
+            self.send_event(id, GameEvent::Save(player));
+        }
+
+        let _ = self.active_entities.saturating_sub(1);
+
+        self.players.write().remove(&id);
+    }
+
+    /// Sends a `GameEvent` to the broker. Output for players is held until the
+    /// end of the tick, so each player gets all of theirs in one batch.
     pub fn send_event(&self, id: PlayerId, event: GameEvent) {
-        let _ = self.broker.send(Event::Game(id, event));
+        match event {
+            GameEvent::Save(_)
+            | GameEvent::GlobalSave(_)
+            | GameEvent::Ban(..)
+            | GameEvent::OfflineTell(..)
+            | GameEvent::Mail(_)
+            | GameEvent::Ignore(..)
+            | GameEvent::PeerReport(_) => {
+                let _ = self.broker.send(Event::Game(id, event));
+            }
+            event => self.outbox.lock().push(id, event),
+        }
+    }
+
+    /// Sends each player the output they were sent during this tick.
+    fn flush_output(&self) {
+        for (id, events) in self.outbox.lock().drain() {
+            let _ = self.broker.send(Event::Game(id, GameEvent::Batch(events)));
+        }
     }
 
     /// Sends a `GameEvent::Command` to the broker. This is just a wrapper
@@ -196,40 +488,65 @@ impl World {
         self.send_event(id, GameEvent::Command(response));
     }
 
+    /// Sends a GMCP package to the broker. This is how commands and systems
+    /// emit out-of-band data next to their regular `Response`. Packages sent to
+    /// clients which have not negotiated GMCP are dropped by the connection.
+    pub fn send_gmcp(&self, id: PlayerId, package: Package) {
+        self.send_event(id, GameEvent::Gmcp(package));
+    }
+
     /// Moves the server time ahead by one tick, as defined in the config file.
     fn tick(&mut self) {
-        self.timer.count += 1;
+        let seconds = self.timer.seconds;
 
-        if self.timer.count % self.timer.tick_rate == 0 {
-            self.timer.seconds += 1;
+        match self.timer.advance(Instant::now()) {
+            Schedule::Wait(duration) => sleep(duration),
+            Schedule::CatchUp(late) => self.systems.execution_timer.overrun(late, 0),
+            Schedule::Skip { late, ticks } => self.systems.execution_timer.overrun(late, ticks),
         }
 
-        // Runs the server at 20 ticks per second
-        sleep(Duration::from_millis(self.timer.interval));
+        if self.timer.seconds != seconds {
+            self.update_status();
+        }
+    }
+
+    /// Refreshes the shared server status snapshot. This runs once a second,
+    /// which is more than fresh enough for MUD listing crawlers.
+    fn update_status(&self) {
+        let players = self.players.read().len();
+        self.status.write().players = players;
     }
 
     // Adds a system to the world with mutable access to both the world and its
-    // parent struct. Systems run once per server tick and run in the order they
-    // were added.
-    pub fn add_system(&mut self, name: &'static str, system: impl System + 'static) -> &mut Self {
+    // parent struct. Systems run as often as their schedule says, in stage
+    // order, and otherwise in the order they were added.
+    pub fn add_system(
+        &mut self,
+        name: &'static str,
+        system: impl System + 'static,
+        schedule: SystemSchedule,
+    ) -> &mut Self {
         self.systems
             .write
-            .push(SystemHandle::new(name, Box::new(system)));
+            .push(SystemHandle::new(name, Box::new(system), schedule));
+        self.systems.sort();
 
         self
     }
 
     // Adds a system to the world with read-only access to both the world and
-    // its parent struct. Systems run once per server tick and run in the order
-    // they were added.
+    // its parent struct. Readonly systems run before the others each tick, and
+    // are scheduled the same way.
     pub fn add_system_readonly(
         &mut self,
         name: &'static str,
         system: impl SystemReadOnly + 'static,
+        schedule: SystemSchedule,
     ) -> &mut Self {
         self.systems
             .readonly
-            .push(SystemReadOnlyHandle::new(name, Box::new(system)));
+            .push(SystemReadOnlyHandle::new(name, Box::new(system), schedule));
+        self.systems.sort();
 
         self
     }
@@ -254,6 +571,7 @@ impl World {
         self.commands.push(CommandHandle {
             inner: command,
             func: Box::new(func),
+            timings: Timings::new(),
         });
 
         // Create a mapping from each key (name or alias) to the index that the
@@ -277,10 +595,7 @@ impl World {
         // with the prompt. It is more idiomatic than handling this in the
         // command itself.
         if let Some(player) = self.players.read().get(&id) {
-            self.send_event(
-                id,
-                GameEvent::Command(Response::Client(format!("{}", Prompt::from(player)))),
-            );
+            self.send_event(id, GameEvent::Prompt(Prompt::from(player)));
         }
     }
 
@@ -336,11 +651,16 @@ impl Default for World {
 
 impl std::fmt::Display for World {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let output = format!("Blossom World Stats\nUptime: {}\nAverage Execution Time: {}\nConnections: {}\nSystems: {}\nEntity Count: {} active, {} spawned\n{}",
+        let output = format!("Blossom World Stats\nUptime: {}\nAverage Execution Time: {}\nOverruns: {} ({} ticks skipped)\nConnections: {}\nSystems: {}\nCommand Timings:{}\nScheduled Tasks: {}{}\nEntity Count: {} active, {} spawned\n{}",
             self.timer.to_string().bold(),
             self.systems.execution_timer.average().foreground(theme::GREEN).bold(),
+            self.systems.execution_timer.overruns.to_string().foreground(theme::YELLOW).bold(),
+            self.systems.execution_timer.skipped.to_string().foreground(theme::YELLOW).bold(),
             self.players.read().len().to_string().foreground(theme::GREEN).bold(),
             self.systems,
+            self.command_timings(),
+            self.scheduler.len().to_string().foreground(theme::GREEN).bold(),
+            self.scheduler.summary(self.timer.count),
             self.active_entities.to_string().foreground(theme::GREEN).bold(),
             self.spawned_entities.to_string().foreground(theme::YELLOW).bold(),
             self.monsters
@@ -381,7 +701,11 @@ mod tests {
             }
         }
 
-        world.add_system("test_system", TestSystem { count: 0 });
+        world.add_system(
+            "test_system",
+            TestSystem { count: 0 },
+            SystemSchedule::every_tick(),
+        );
 
         assert_eq!(world.systems.write.len(), 1);
         assert_eq!(world.systems.write[0].name, "test_system");
@@ -397,12 +721,56 @@ mod tests {
             fn update(&self, _: &World) {}
         }
 
-        world.add_system_readonly("test_system", TestSystem);
+        world.add_system_readonly("test_system", TestSystem, SystemSchedule::every_tick());
 
         assert_eq!(world.systems.readonly.len(), 1);
         assert_eq!(world.systems.readonly[0].name, "test_system");
     }
 
+    #[test]
+    fn panicking_system_is_stopped() {
+        let mut world = World::new();
+
+        struct Panics;
+
+        impl System for Panics {
+            fn update(&mut self, _: &mut World) {
+                panic!("oops");
+            }
+        }
+
+        struct Counts(Arc<Mutex<u8>>);
+
+        impl System for Counts {
+            fn update(&mut self, _: &mut World) {
+                *self.0.lock() += 1;
+            }
+        }
+
+        let count = Arc::new(Mutex::new(0));
+        world.add_system("panics", Panics, SystemSchedule::every_tick());
+        world.add_system(
+            "counts",
+            Counts(Arc::clone(&count)),
+            SystemSchedule::every_tick(),
+        );
+
+        world.run_systems();
+        world.run_systems();
+
+        let status = |name| {
+            world
+                .systems
+                .write
+                .iter()
+                .find(|s| s.name == name)
+                .map(|s| s.status)
+        };
+        assert_eq!(status("panics"), Some(SystemStatus::Stopped));
+        assert_eq!(status("counts"), Some(SystemStatus::Running));
+        assert_eq!(*count.lock(), 2);
+    }
+
     #[test]
     fn advance_tick() {
         let mut world = World::new();
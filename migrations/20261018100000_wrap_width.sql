@@ -0,0 +1,2 @@
+alter table blossom.players
+    add column if not exists wrap_width int default 80 not null;
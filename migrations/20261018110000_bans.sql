@@ -0,0 +1,30 @@
+create table if not exists blossom.bans
+(
+    id          serial primary key unique not null,
+    ip_range    cidr,
+    account_id  int,
+    reason      text                      not null,
+    banned_by   int,
+    expires_on  timestamptz,
+    lifted      boolean default false     not null,
+
+    /* Constraints */
+    constraint fk_account foreign key (account_id) references accounts (id),
+    constraint fk_banned_by foreign key (banned_by) references accounts (id),
+    constraint ban_target check (ip_range is not null or account_id is not null),
+
+    /* Meta */
+    created_on  timestamptz default now() not null,
+    modified_on timestamptz default now() not null
+);
+
+create index if not exists bans_ip_range_idx on blossom.bans using gist (ip_range inet_ops);
+create index if not exists bans_account_id_idx on blossom.bans (account_id);
+
+drop trigger if exists on_modify_ban ON "blossom"."bans";
+
+create trigger on_modify_ban
+    before insert or update
+    on bans
+    for each row
+execute procedure update_modified_on();
@@ -0,0 +1,2 @@
+alter table blossom.players
+    add column if not exists channels text[];
@@ -0,0 +1,26 @@
+create table if not exists blossom.tells
+(
+    id           serial primary key unique not null,
+    sender       int                       not null,
+    recipient    int                       not null,
+    message      text                      not null,
+    delivered_on timestamptz,
+
+    /* Constraints */
+    constraint fk_sender foreign key (sender) references players (id),
+    constraint fk_recipient foreign key (recipient) references players (id),
+
+    /* Meta */
+    created_on   timestamptz default now() not null,
+    modified_on  timestamptz default now() not null
+);
+
+create index if not exists tells_pending_idx on blossom.tells (recipient) where delivered_on is null;
+
+drop trigger if exists on_modify_tell ON "blossom"."tells";
+
+create trigger on_modify_tell
+    before insert or update
+    on tells
+    for each row
+execute procedure update_modified_on();
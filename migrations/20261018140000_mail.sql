@@ -0,0 +1,27 @@
+create table if not exists blossom.mail
+(
+    id          serial primary key unique not null,
+    sender      int                       not null,
+    recipient   int                       not null,
+    subject     text                      not null,
+    body        text                      not null,
+    read        boolean default false     not null,
+
+    /* Constraints */
+    constraint fk_sender foreign key (sender) references players (id),
+    constraint fk_recipient foreign key (recipient) references players (id),
+
+    /* Meta */
+    created_on  timestamptz default now() not null,
+    modified_on timestamptz default now() not null
+);
+
+create index if not exists mail_recipient_idx on blossom.mail (recipient);
+
+drop trigger if exists on_modify_mail ON "blossom"."mail";
+
+create trigger on_modify_mail
+    before insert or update
+    on mail
+    for each row
+execute procedure update_modified_on();
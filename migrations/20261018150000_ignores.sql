@@ -0,0 +1,24 @@
+create table if not exists blossom.ignores
+(
+    account_id  int                       not null,
+    -- The character the player named. Their whole account is ignored, so
+    -- switching characters doesn't get around it.
+    player_id   int                       not null,
+
+    /* Constraints */
+    constraint pk_ignores primary key (account_id, player_id),
+    constraint fk_account foreign key (account_id) references accounts (id),
+    constraint fk_player foreign key (player_id) references players (id),
+
+    /* Meta */
+    created_on  timestamptz default now() not null,
+    modified_on timestamptz default now() not null
+);
+
+drop trigger if exists on_modify_ignore ON "blossom"."ignores";
+
+create trigger on_modify_ignore
+    before insert or update
+    on ignores
+    for each row
+execute procedure update_modified_on();